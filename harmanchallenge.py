@@ -30,12 +30,81 @@ Usage:
     ./harmanchallenge.py run --input=input/hello.mp4 --width=640 --height=480 --format=h264 --flip --invert
 
     Args:
-        --input: path to the input video file (mp4 supported only)
+        --input: path to the input video file (mp4 supported only), numbered images (e.g.
+                 frame_%04d.png), http(s)/rtsp/udp/rtp/srt URI or - for stdin (MPEG-TS or
+                 Matroska); the webcam is used if missing. Repeat it, or give a playlist
+                 (*.m3u, *.m3u8, *.txt), to play several files one after another into one video.
+                 Capture devices (/dev/video2) and test://PATTERN are inputs too
+        --transition: cut, crossfade, dip-to-black or wipe[:SECONDS] between files played in a
+                  row; once for all the joins, or once per join (optional)
+        --latency: network input latency in ms (optional)
+        --reconnect: how many times to reconnect to the network input after an error (optional)
+        --reconnect-delay: seconds between reconnection attempts (optional)
+        --test-pattern: synthetic input pattern instead of a file or webcam, e.g. smpte, ball (optional)
+        --num-frames: number of test pattern frames to produce (optional)
+        --device: webcam device, e.g. /dev/video2 (optional)
+        --source-width, --source-height: resolution requested from the webcam or test pattern, or of
+                  the raw input (optional)
+        --framerate: framerate requested from the webcam or test pattern, of the images or raw
+                  input, or of several files played in a row (optional)
+        --raw-format: read the input file as headerless raw i420, nv12, yuy2, rgb, bgr or rgba video,
+                  of --source-width x --source-height (optional)
+        --pixel-format: format requested from the webcam: raw, mjpeg or h264 (optional)
+        --pip: draw the webcam (this device, or the default one) over the input (optional)
+        --pip-position, --pip-size, --pip-margin, --pip-border, --pip-opacity: corner (default
+                  bottom-right), size (default 320x180), margin (default 16), border width
+                  (default 0) and opacity (default 1) of the inset (optional)
+        --mosaic: tile all the --input into a labeled grid of --width x --height instead (optional)
+        --mosaic-columns: number of columns of the grid; enough for a square grid by default (optional)
+        --mosaic-label: label of each tile, in input order; the input's name by default, none if
+                  empty (optional)
+        --output: screen (default), rtp://host:port to stream the video over RTP/UDP, a file to record
+                  to (*.mp4, *.mov, *.mkv, *.ts), an HLS playlist (*.m3u8) / DASH manifest (*.mpd)
+                  to write segments for, raw frames (*.yuv, *.rgb), an animated preview
+                  (*.gif, *.webp), or - for stdout (MPEG-TS); can be repeated, and each output
+                  can have its own ,width=W,height=H and ,format=F (optional)
+        --sdp: file where an SDP description of the RTP output is written (optional)
+        --split-time, --split-size: split file outputs every N seconds / N MB, on keyframes,
+                  into rec_00000.mp4, rec_00001.mp4, ... (optional)
+        --pre-event: keep the last N seconds in memory and record them with the next --post-event
+                  seconds to a new file on SIGUSR1 or "trigger" on stdin (dashcam-style) (optional)
+        --post-event: seconds recorded after each trigger (default: 10) (optional)
+        --segment-format: ts or fmp4 HLS/DASH segments (optional)
+        --segment-duration: target HLS/DASH segment duration in seconds (default: 6) (optional)
+        --playlist-length: number of segments in the HLS playlist, 0 for all (default: 5) (optional)
+        --renditions: HLS/DASH resolutions to encode from one decode, e.g. 1280x720,640x360 (optional)
+        --stdout-container: ts or mkv container of the - output (default: ts) (optional)
+        --raw-output-format: pixel format of the *.yuv/*.rgb outputs (default: i420 / rgb) (optional)
+        --animation-fps: frame rate of the GIF/WebP outputs (default: 10) (optional)
+        --animation-max-width: maximum width of the GIF/WebP outputs, 0 for none (default: 480) (optional)
+        --start, --duration: process only part of the input file, in seconds (optional)
         --width: width in px of the output video (optional)
         --height: height in px of the output video (optional)
         --format: format for encoding the video before passing it to the screen sink (h264 supported only) (optional)
         --flip: flip the output video horizontally (optional)
         --invert: invert colors on the output video (optional)
+        --filter-bin: custom filter as a gst-launch-1.0 fragment, applied before encoding; can be repeated (optional)
+        --dry-run: print the equivalent gst-launch-1.0 command line instead of playing (optional)
+        --dump-graph: directory for DOT graphs of the pipeline, written on state changes and errors (optional)
+        --graph-format: also render the DOT graphs to svg or png, if dot is installed (optional)
+        -v/-q: raise/lower the log level (default: warn) (optional)
+        --log-level: off, error, warn, info, debug or trace; also applies to gstreamer's messages (optional)
+
+./harmanchallenge.py run devices
+    Lists the video capture devices and the modes (formats, resolutions, framerates) they support.
+
+./harmanchallenge.py run [args] serve-rtsp [--port=8554] [--mount=/stream]
+    Publishes the processed video at rtsp://0.0.0.0:<port><mount> with a built-in RTSP server;
+    all the clients share the same pipeline.
+
+./harmanchallenge.py run [args] extract-frames --pattern=frames/frame_%05d.png [--every=N|--interval=S|--at=S,...|--keyframes]
+    Writes frames of the processed video to PNG or JPEG files instead of playing it: every frame,
+    every Nth frame, every S seconds, at the given timestamps or only the keyframes.
+    %d (or %05d, ...) in the file name is the frame number, %t the timestamp in milliseconds.
+
+./harmanchallenge.py run --input=$INFILE contact-sheet --image=sheet.jpg [--columns=4] [--rows=4] [--tile-width=320] [--tile-height=H] [--timestamps]
+    Writes a grid of evenly spaced frames of the input file to one PNG or JPEG image,
+    optionally with the timestamp of each frame under its tile.
 
 ./harmanchallenge.py doc
     Runs the docker container `{}`, mounts the project directory as `{}`, 
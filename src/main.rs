@@ -5,7 +5,128 @@
 //! ## Usage
 //!
 //! ```bash
-//! cargo run -- --input=$INFILE [--width=$W] [--height=$H] [--format=$FORMAT] [--flip] [--invert]
+//! cargo run -- --input=$INFILE|$IMAGES|$URI|$PLAYLIST|$DEV|test://$PATTERN|- [--input=$INFILE ...] [--transition=$KIND[:$S] ...]
+//!     [--mosaic [--mosaic-columns=$N] [--mosaic-label=$TEXT ...]]
+//!     [--latency=$MS] [--reconnect=$N] [--reconnect-delay=$S]
+//!     [--width=$W] [--height=$H] [--format=$FORMAT] [--flip] [--invert]
+//!     [--test-pattern=$PATTERN [--num-frames=$N]]
+//!     [--device=$DEV] [--source-width=$W] [--source-height=$H] [--framerate=$FPS] [--pixel-format=$PIXFMT]
+//!     [--pip[=$DEV] [--pip-position=$CORNER] [--pip-size=$WxH] [--pip-margin=$PX] [--pip-border=$PX] [--pip-opacity=$A]]
+//!     [--raw-format=$RAWFMT] [--raw-output-format=$RAWFMT] [--stdout-container=ts|mkv]
+//!     [--output=screen|-|rtp://$HOST:$PORT|$OUTFILE|$PLAYLIST.m3u8|$MANIFEST.mpd|$RAWFILE.yuv|$PREVIEW.gif|$PREVIEW.webp[,width=$W,height=$H][,format=$FORMAT] ...]
+//!     [--sdp=$SDPFILE] [--split-time=$S] [--split-size=$MB] [--pre-event=$S [--post-event=$S]]
+//!     [--segment-format=ts|fmp4] [--segment-duration=$S] [--playlist-length=$N] [--renditions=$WxH,...]
+//!     [--animation-fps=$FPS] [--animation-max-width=$W] [--start=$S] [--duration=$S]
+//!     [--dry-run] [-v|-q] [--log-level=$LEVEL]
+//! ```
+//!
+//! Logs go to stderr. The default level is `warn`; each `-v` raises it by one step, each `-q`
+//! lowers it, and `--log-level` sets it explicitly. `gstreamer`'s own debug output is forwarded
+//! to the same sink with the same level, e.g. `--log-level=debug` shows both.
+//!
+//! To list the capture devices and the modes they support:
+//!
+//! ```bash
+//! cargo run -- devices
+//! ```
+//!
+//! To watch the webcam and record it at the same time, at full HD:
+//!
+//! ```bash
+//! cargo run -- --output=screen --output=rec.mp4,width=1920,height=1080
+//! ```
+//!
+//! With `--split-time` and/or `--split-size`, file outputs are split into `rec_00000.mp4`,
+//! `rec_00001.mp4`, ... on keyframes, and each finished file is announced on stdout.
+//!
+//! With `--pre-event`, file outputs become event recordings instead, dashcam-style: the last
+//! seconds of video are kept in memory and, on SIGUSR1 or a `trigger` line on stdin, written to
+//! `rec_00000.mp4`, ... along with the following `--post-event` seconds:
+//!
+//! ```bash
+//! cargo run -- --output=screen --output=rec.mp4 --pre-event=20 --post-event=10
+//! kill -USR1 $PID
+//! ```
+//!
+//! To write HLS for a web player instead, at two resolutions from one decode:
+//!
+//! ```bash
+//! cargo run -- --input=input/hello.mp4 --output=out/hls/stream.m3u8 --renditions=1280x720,640x360
+//! ```
+//!
+//! To turn numbered stills (`frame_0001.png`, `frame_0002.png`, ...) into a timelapse video:
+//!
+//! ```bash
+//! cargo run -- --input=stills/frame_%04d.png --framerate=30 --output=timelapse.mp4
+//! ```
+//!
+//! To run a raw test vector through the filters and keep the raw result, for diffing against a
+//! reference:
+//!
+//! ```bash
+//! cargo run -- --input=foreman_352x288.yuv --raw-format=i420 --source-width=352 --source-height=288 \
+//!     --flip --output=flipped.yuv
+//! ```
+//!
+//! To process a stream in a shell pipeline, from stdin to stdout (MPEG-TS or Matroska):
+//!
+//! ```bash
+//! curl -s $URL/clip.ts | cargo run -- --input=- --invert --output=- | ffplay -
+//! ```
+//!
+//! To join the parts of a recording into one file, at the resolution and framerate of the first
+//! part (or `--width`, `--height` and `--framerate`):
+//!
+//! ```bash
+//! cargo run -- --input=standup_1.mp4 --input=standup_2.mp4 --output=standup.mp4
+//! cargo run -- --input=standup.m3u --output=standup.mp4
+//! ```
+//!
+//! The same with a one second crossfade between the parts, sound included, from a GES timeline:
+//!
+//! ```bash
+//! cargo run -- --input=standup.m3u --transition=crossfade:1 --output=standup.mp4
+//! ```
+//!
+//! To record a talk with the presenter's webcam in the top right corner of the slides:
+//!
+//! ```bash
+//! cargo run -- --input=screen.mp4 --pip=/dev/video0 --pip-position=top-right --pip-size=320x180 \
+//!     --pip-border=4 --pip-opacity=0.9 --output=talk.mp4
+//! ```
+//!
+//! To watch three cameras and a stream at once, in a labeled 2x2 grid, and record the wall:
+//!
+//! ```bash
+//! cargo run -- --input=/dev/video0 --input=/dev/video2 --input=/dev/video4 --input=rtsp://cam4/stream \
+//!     --mosaic --mosaic-label=Lobby --mosaic-label=Lab --output=screen --output=wall.mp4
+//! ```
+//!
+//! To make a 5 second animated GIF of a recording, for a ticket:
+//!
+//! ```bash
+//! cargo run -- --input=input/hello.mp4 --output=clip.gif --start=62 --duration=5 --animation-max-width=320
+//! ```
+//!
+//! To publish the processed video with a built-in RTSP server, shared by all the clients:
+//!
+//! ```bash
+//! cargo run -- --input=$INFILE|$URI [...] serve-rtsp [--port=8554] [--mount=/stream]
+//! ```
+//!
+//! To write one frame per second of the processed video to PNG files instead of playing it:
+//!
+//! ```bash
+//! cargo run -- --input=input/hello.mp4 --flip extract-frames --pattern=frames/frame_%05d.png --interval=1
+//! ```
+//!
+//! Frames can also be picked with `--every=$N`, `--at=$S,...` or `--keyframes`, and `%t` in the
+//! pattern stands for the timestamp in milliseconds. Each image is announced on stdout.
+//!
+//! To review a long recording at a glance, as a 4x4 grid of evenly spaced frames in one image:
+//!
+//! ```bash
+//! cargo run -- --input=input/hello.mp4 contact-sheet --image=sheet.jpg --timestamps
 //! ```
 //!
 //! ## Example
@@ -17,18 +138,29 @@
 //! cargo run -- --input=input/hello.mp4 --width=640 --height=480 --format=h264 --flip --invert
 //! ```
 //!
-//! This is the equivalent of the following [`gstreamer`](https://gstreamer.freedesktop.org/) pipeline:
+//! This is roughly the equivalent of the following [`gstreamer`](https://gstreamer.freedesktop.org/) pipeline:
 //!
 //! ```bash
 //! gst-launch-1.0 filesrc location=input/hello.mp4 !   \
 //!     qtdemux name=demux demux.video_0 !              \
 //!     avdec_h264 ! videoconvert !                     \
 //!     coloreffects preset=3 ! videoconvert !          \
-//!     videoscale ! video/x-raw,width=600,height=400 ! \
+//!     videoscale ! video/x-raw,width=640,height=480 ! \
 //!     videoflip method=horizontal-flip !              \
-//!     xvimagesink
+//!     x264enc tune=zerolatency ! queue !              \
+//!     avdec_h264 ! videoconvert ! xvimagesink
+//! ```
+//!
+//! To stream the result over RTP instead, and play it with another instance:
+//!
+//! ```bash
+//! cargo run -- --input=input/hello.mp4 --output=rtp://127.0.0.1:5000 --sdp=stream.sdp
+//! cargo run -- --input=rtp://:5000
 //! ```
 //!
+//! The exact pipeline, with every property and caps, is printed by adding `--dry-run`;
+//! the video is not played in that case.
+//!
 //! ## Links
 //!
 //! See also:
@@ -36,23 +168,110 @@
 //! * the [`video`] documentation
 
 use std::borrow::Borrow;
+use std::io;
+use std::process;
+use std::thread;
 
-use util::{Cli, DecoderOptions};
+use util::{
+    Cli, Command, ConcatOptions, DecoderOptions, Input, Output, TestPatternOptions, WebcamOptions,
+};
 use video::{
-    gst::{self, GstreamerDecoder},
-    Decoder, VideoInput,
+    gst::{self, timeline::TimelineDecoder, GstreamerDecoder, Interrupt, Trigger},
+    Decoder, Error, VideoInput,
 };
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use log::{error, info, warn};
+use signal_hook::consts::{SIGINT, SIGUSR1};
+use signal_hook::iterator::Signals;
 
 fn main() {
     let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.log_level())
+        .init();
+
+    if let Err(e) = run(cli) {
+        error!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Error> {
+    gst::logging::init_logging(cli.log_level())?;
+
+    if let Some(Command::Devices) = cli.command {
+        for device in video::list_devices()? {
+            print!("{}", device);
+        }
+        return Ok(());
+    }
+
+    let inputs = cli.inputs();
+    if let Some(sheet) = cli.command.as_ref().and_then(Command::contact_sheet) {
+        let [Input::File(path)] = inputs.as_slice() else {
+            Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "contact-sheet needs a single file --input",
+                )
+                .exit();
+        };
+        video::contact_sheet(path, &sheet)?;
+        println!("{}", sheet.image.display());
+        return Ok(());
+    }
+
     let opts: DecoderOptions = cli.borrow().into();
 
-    let insrc: VideoInput = match cli.input {
-        Some(path) => VideoInput::File(path.as_path().to_string_lossy().to_string()),
-        None => VideoInput::Webcam,
+    let insrc: VideoInput = match (inputs.as_slice(), cli.mosaic_options()) {
+        ([], _) if cli.test_pattern.is_some() => VideoInput::TestPattern(cli.borrow().into()),
+        ([], _) => VideoInput::Webcam(cli.borrow().into()),
+        // Several inputs side by side
+        (inputs, Some(mosaic)) => VideoInput::Mosaic(mosaic_tiles(&cli, inputs), mosaic),
+        ([input], None) => video_input(&cli, input),
+        // Several files (or a playlist) are played one after another
+        (inputs, None) => VideoInput::Concat(concat_files(&cli, inputs), cli.borrow().into()),
+    };
+    // A webcam inset is drawn over the input
+    let insrc = match cli.pip_options() {
+        Some(_) if matches!(insrc, VideoInput::Concat(..) | VideoInput::Mosaic(..)) => {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--pip needs a single input, not several --input",
+                )
+                .exit()
+        }
+        Some(pip) => VideoInput::PictureInPicture(Box::new(insrc), pip),
+        None => insrc,
     };
+    info!("Input: {}", insrc);
+    for output in &opts.outputs {
+        info!("Output: {}", output);
+    }
+    // stdin and stdout may carry the video, in a shell pipeline
+    let stdin_free = !matches!(insrc, VideoInput::Stdin);
+    let stdout_free = !opts
+        .outputs
+        .iter()
+        .any(|out| matches!(out.target, Output::Stdout { .. }));
+
+    // Transitions between files are played from a GES timeline instead of the usual pipeline
+    if matches!(&insrc, VideoInput::Concat(_, concat) if concat.has_transitions()) {
+        let timeline_mutex = TimelineDecoder::new(insrc)?;
+        TimelineDecoder::build(timeline_mutex.clone(), opts)?;
+
+        let mut lock = timeline_mutex.lock();
+        let timeline = lock.as_deref_mut().map_err(|_| Error::PoisonedLock)?;
+        if cli.dry_run {
+            println!("{}", timeline.describe());
+            return Ok(());
+        }
+        listen_for_signals(Some(timeline.interrupt()), None);
+        return timeline.run();
+    }
 
     // Why an Arc<Mutex> when we can't see any threads?
     // Because Rust is paranoid.
@@ -62,11 +281,166 @@ fn main() {
     // view can be executed on any other thread, and supersede the decoder instance's lifetime too.
     // Conceptually this scenario makes no sense but I can't defeat the compiler sooo, Arc<Mutex>
     // to enforce thread safety and avoid lifetime headaches
-    let decoder_mutex = gst::GstreamerDecoder::new(insrc).unwrap();
+    let decoder_mutex = gst::GstreamerDecoder::new(insrc)?;
 
-    GstreamerDecoder::build(decoder_mutex.clone(), opts).unwrap();
+    GstreamerDecoder::build(decoder_mutex.clone(), opts)?;
 
     let mut lock = decoder_mutex.lock();
-    let decoder = lock.as_deref_mut().unwrap();
-    decoder.run().unwrap();
+    let decoder = lock.as_deref_mut().map_err(|_| Error::PoisonedLock)?;
+    if cli.dry_run {
+        println!("{}", decoder.describe());
+        return Ok(());
+    }
+    // Announce the finished files of split and event recordings and the extracted frames on
+    // stdout, for scripts to pick up, unless the video goes there
+    if stdout_free {
+        decoder.on_event(|event| println!("{}", event));
+    } else {
+        decoder.on_event(|event| eprintln!("{}", event));
+    }
+    let trigger = decoder.trigger();
+    listen_for_signals(decoder.interrupt(), trigger.clone());
+    if let Some(trigger) = trigger.filter(|_| stdin_free) {
+        listen_for_triggers(trigger);
+    }
+    decoder.run()
+}
+
+/// The video input of a single `--input`; capture devices and test patterns take the
+/// webcam and test pattern options
+fn video_input(cli: &Cli, input: &Input) -> VideoInput {
+    match input {
+        Input::File(path) if cli.raw_format.is_some() => {
+            VideoInput::Raw(path.as_path().to_string_lossy().to_string(), cli.into())
+        }
+        Input::File(path) => VideoInput::File(path.as_path().to_string_lossy().to_string()),
+        Input::Uri(uri) => VideoInput::Uri(uri.clone(), cli.into()),
+        Input::ImageSequence { pattern, .. } => {
+            VideoInput::ImageSequence(pattern.as_path().to_string_lossy().to_string(), cli.into())
+        }
+        Input::Stdin => VideoInput::Stdin,
+        Input::Device(path) => VideoInput::Webcam(WebcamOptions {
+            device: Some(path.as_path().to_string_lossy().to_string()),
+            ..cli.into()
+        }),
+        Input::TestPattern(pattern) => VideoInput::TestPattern(TestPatternOptions {
+            pattern: pattern.clone(),
+            ..cli.into()
+        }),
+        // Playlists are replaced by their files in Cli::inputs
+        Input::Playlist(files) => VideoInput::Concat(
+            files
+                .iter()
+                .map(|path| path.as_path().to_string_lossy().to_string())
+                .collect(),
+            cli.into(),
+        ),
+    }
+}
+
+/// The tiles of a mosaic, one per input; there can't be more labels than inputs
+fn mosaic_tiles(cli: &Cli, inputs: &[Input]) -> Vec<VideoInput> {
+    if cli.mosaic_label.len() > inputs.len() {
+        Cli::command()
+            .error(
+                ErrorKind::TooManyValues,
+                format!(
+                    "{} --mosaic-label for {} inputs",
+                    cli.mosaic_label.len(),
+                    inputs.len()
+                ),
+            )
+            .exit();
+    }
+    inputs.iter().map(|input| video_input(cli, input)).collect()
+}
+
+/// The files of several inputs played one after another; only plain video files can be
+/// concatenated, with either one transition for all the joins or one per join
+fn concat_files(cli: &Cli, inputs: &[Input]) -> Vec<String> {
+    let files: Vec<String> = inputs
+        .iter()
+        .filter_map(|input| match input {
+            Input::File(path) => Some(path.as_path().to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    if files.len() != inputs.len() || cli.raw_format.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "only video files can be played one after another with several --input",
+            )
+            .exit();
+    }
+    let transitions = ConcatOptions::from(cli).transitions.len();
+    if transitions > 1 && transitions != files.len() - 1 {
+        Cli::command()
+            .error(
+                ErrorKind::WrongNumberOfValues,
+                format!(
+                    "{} --transition for {} joins: give one for all of them, or one per join",
+                    transitions,
+                    files.len() - 1
+                ),
+            )
+            .exit();
+    }
+    files
+}
+
+/// Handle the signals the pipeline reacts to: on Ctrl-C (SIGINT), end it cleanly instead of
+/// dying, so that the outputs are finalized, and exit right away on a second Ctrl-C; on SIGUSR1,
+/// start an event recording
+fn listen_for_signals(interrupt: Option<Interrupt>, trigger: Option<Trigger>) {
+    let mut handled = vec![];
+    if interrupt.is_some() {
+        handled.push(SIGINT);
+    }
+    if trigger.is_some() {
+        handled.push(SIGUSR1);
+    }
+    if handled.is_empty() {
+        return;
+    }
+
+    match Signals::new(&handled) {
+        Ok(mut signals) => {
+            thread::spawn(move || {
+                let mut interrupted = false;
+                for signal in signals.forever() {
+                    match (signal, &interrupt, &trigger) {
+                        (SIGINT, Some(_), _) if interrupted => process::exit(130),
+                        (SIGINT, Some(interrupt), _) => {
+                            interrupted = true;
+                            info!("Interrupted");
+                            interrupt.finish();
+                        }
+                        (SIGUSR1, _, Some(trigger)) => {
+                            info!("Event triggered by SIGUSR1");
+                            trigger.fire();
+                        }
+                        _ => (),
+                    }
+                }
+            });
+        }
+        Err(e) => warn!("Signals can't be handled: {}", e),
+    }
+}
+
+/// Start event recordings on each "trigger" line on stdin
+fn listen_for_triggers(trigger: Trigger) {
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            match line.trim() {
+                "trigger" => {
+                    info!("Event triggered from stdin");
+                    trigger.fire();
+                }
+                "" => (),
+                other => warn!("Unknown command \"{}\", expected \"trigger\"", other),
+            }
+        }
+    });
 }
@@ -3,12 +3,13 @@
 
 //! # Utilities
 
-use clap::{command, Parser};
+use clap::{ArgAction, ArgGroup, Parser, Subcommand};
+use log::{debug, warn, LevelFilter};
 
 use std::error;
 use std::fmt::{self, Display};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Argument validators
 pub mod validator;
@@ -28,6 +29,569 @@ impl Display for VideoFormat {
     }
 }
 
+#[derive(Clone, Debug)]
+/// Where the input video comes from
+pub enum Input {
+    /// Local file
+    File(PathBuf),
+    /// Network stream (http, https, rtsp, udp, rtp or srt URI)
+    Uri(String),
+    /// Numbered image files, e.g. `frame_%04d.png`
+    ImageSequence {
+        /// File name pattern, with a %d-style placeholder for the file number
+        pattern: PathBuf,
+        /// Number of the first file
+        start_index: u32,
+    },
+    /// Standard input (`-`), in a streamable container such as MPEG-TS or Matroska
+    Stdin,
+    /// Playlist file (`*.m3u`, `*.m3u8`, `*.txt`): local files played one after another
+    Playlist(Vec<PathBuf>),
+    /// Capture device, e.g. `/dev/video2`
+    Device(PathBuf),
+    /// Synthetic test pattern (`test://PATTERN`, e.g. `test://ball`)
+    TestPattern(String),
+}
+
+impl Input {
+    /// Short name of the input, e.g. to label it on screen: the file name, the URI, the device
+    /// or the test pattern
+    pub fn label(&self) -> String {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string())
+        };
+        match self {
+            Input::File(path) => file_name(path),
+            Input::Uri(uri) => uri.clone(),
+            Input::ImageSequence { pattern, .. } => file_name(pattern),
+            Input::Stdin => "stdin".to_string(),
+            Input::Playlist(files) => format!("{} files", files.len()),
+            Input::Device(path) => path.display().to_string(),
+            Input::TestPattern(pattern) => pattern.clone(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Container of the segments of HLS and DASH outputs
+pub enum SegmentFormat {
+    /// MPEG transport stream
+    Ts,
+    /// Fragmented MP4 (CMAF)
+    Fmp4,
+}
+
+impl Display for SegmentFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SegmentFormat::Ts => write!(f, "ts"),
+            SegmentFormat::Fmp4 => write!(f, "fmp4"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Segmented (HLS or DASH) output options
+pub struct SegmentOptions {
+    /// Playlist (.m3u8) or manifest (.mpd) to write; segments are written next to it
+    pub manifest: PathBuf,
+    /// Segment container; defaults to TS for HLS and fragmented MP4 for DASH
+    pub segment_format: Option<SegmentFormat>,
+    /// Target segment duration, in seconds
+    pub segment_duration: u32,
+    /// Number of segments kept in the playlist; 0 keeps all of them
+    pub playlist_length: u32,
+    /// Resolutions (width x height) to encode, each as a separate rendition;
+    /// a single rendition at the processed resolution if empty
+    pub renditions: Vec<(i32, i32)>,
+}
+
+impl SegmentOptions {
+    /// Segmented output options with the default segmenting parameters
+    pub fn new(manifest: PathBuf) -> Self {
+        SegmentOptions {
+            manifest,
+            segment_format: None,
+            segment_duration: 6,
+            playlist_length: 5,
+            renditions: vec![],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Container of file outputs
+pub enum Container {
+    /// MP4 (.mp4, .mov)
+    Mp4,
+    /// Matroska (.mkv)
+    Matroska,
+    /// MPEG transport stream (.ts)
+    MpegTs,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+/// When a recording is split into a new file, whichever comes first.
+/// Files are always split on keyframes, so each of them plays on its own
+pub struct SplitOptions {
+    /// Maximum duration of a file, in seconds
+    pub max_time_secs: Option<u64>,
+    /// Maximum size of a file, in megabytes
+    pub max_size_mb: Option<u64>,
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Image file formats
+pub enum ImageFormat {
+    /// PNG (.png)
+    Png,
+    /// JPEG (.jpg, .jpeg)
+    Jpeg,
+}
+
+impl ImageFormat {
+    /// The image format matching the extension of `path`, if supported
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Which frames are extracted to image files
+pub enum FrameSelection {
+    /// Every Nth frame, starting with the first one
+    Every(u64),
+    /// The first frame of every N seconds
+    Interval(f64),
+    /// The first frame at or after each of the timestamps, in seconds
+    Timestamps(Vec<f64>),
+    /// The keyframes of the input
+    Keyframes,
+}
+
+#[derive(Clone, Debug)]
+/// Frame extraction options
+pub struct FrameOptions {
+    /// File name pattern of the images: %d (or %05d, ...) is replaced by the frame number,
+    /// %t by the timestamp in milliseconds
+    pub pattern: PathBuf,
+    /// Format of the images, from the pattern's extension
+    pub format: ImageFormat,
+    /// Which frames are extracted
+    pub selection: FrameSelection,
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Animated image formats
+pub enum AnimationFormat {
+    /// Animated GIF (.gif)
+    Gif,
+    /// Animated WebP (.webp)
+    WebP,
+}
+
+impl Display for AnimationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationFormat::Gif => write!(f, "GIF"),
+            AnimationFormat::WebP => write!(f, "WebP"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Animated image options, for short previews
+pub struct AnimationOptions {
+    /// Path of the animation
+    pub path: PathBuf,
+    /// Format of the animation, from the extension of the path
+    pub format: AnimationFormat,
+    /// Frame rate the video is reduced to
+    pub fps: u32,
+    /// Width the video is scaled down to, if wider
+    pub max_width: Option<u32>,
+}
+
+impl AnimationOptions {
+    /// Animation options with the default frame rate (10 fps) and maximum width (480)
+    pub fn new(path: PathBuf, format: AnimationFormat) -> Self {
+        AnimationOptions {
+            path,
+            format,
+            fps: 10,
+            max_width: Some(480),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Contact sheet options: a grid of evenly spaced frames of a video file, in one image
+pub struct ContactSheetOptions {
+    /// Path of the image
+    pub image: PathBuf,
+    /// Format of the image, from its extension
+    pub format: ImageFormat,
+    /// Number of tiles per row
+    pub columns: u32,
+    /// Number of rows
+    pub rows: u32,
+    /// Width of the tiles
+    pub tile_width: u32,
+    /// Height of the tiles; follows the aspect ratio of the video if missing
+    pub tile_height: Option<u32>,
+    /// Print the timestamp of each frame under its tile
+    pub timestamps: bool,
+}
+
+/// Expand a frame file name pattern: `%d` (or `%05d`, ...) becomes `frame`, `%t` becomes
+/// `timestamp_ms` and `%%` a literal `%`
+pub fn expand_frame_pattern(pattern: &str, frame: u64, timestamp_ms: u64) -> String {
+    let mut expanded = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        let mut width = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            width.push(digit);
+        }
+        match chars.peek() {
+            Some('d') => {
+                chars.next();
+                let width = width.parse::<usize>().unwrap_or(0);
+                expanded.push_str(&format!("{:0width$}", frame, width = width));
+            }
+            Some('t') if width.is_empty() => {
+                chars.next();
+                expanded.push_str(&timestamp_ms.to_string());
+            }
+            Some('%') if width.is_empty() => {
+                chars.next();
+                expanded.push('%');
+            }
+            _ => {
+                expanded.push('%');
+                expanded.push_str(&width);
+            }
+        }
+    }
+    expanded
+}
+
+#[derive(Clone, Debug, Default)]
+/// Where the processed video goes
+pub enum Output {
+    /// Rendered on the screen
+    #[default]
+    Screen,
+    /// Sent as an RTP stream over UDP
+    Rtp {
+        /// Destination host
+        host: String,
+        /// Destination port
+        port: u16,
+        /// SDP file describing the stream, for players to open
+        sdp: Option<PathBuf>,
+    },
+    /// Published by a built-in RTSP server, at rtsp://0.0.0.0:port/mount
+    Rtsp {
+        /// Port the server listens on
+        port: u16,
+        /// Path the stream is published at, starting with '/'
+        mount: String,
+    },
+    /// Recorded to a file
+    File {
+        /// File to write
+        path: PathBuf,
+        /// Container, from the file extension
+        container: Container,
+        /// Split the recording into several files, if set; `path` is then a pattern
+        /// such as rec_%05d.mp4
+        split: Option<SplitOptions>,
+    },
+    /// Kept in memory for the last seconds, and recorded to a new file around each event
+    /// (dashcam-style): the buffered pre-event video plus the following seconds
+    EventRecording {
+        /// File to write, numbered for each event (rec.mp4 is written to rec_00000.mp4, ...)
+        path: PathBuf,
+        /// Container, from the file extension
+        container: Container,
+        /// Seconds of video kept from before the event
+        pre_secs: u64,
+        /// Seconds of video recorded after the event
+        post_secs: u64,
+    },
+    /// Selected frames written to image files
+    Frames(FrameOptions),
+    /// Written as an animated GIF or WebP
+    Animation(AnimationOptions),
+    /// Written to standard output (`-`), in a streamable container (MPEG-TS or Matroska)
+    Stdout {
+        /// Container of the stream
+        container: Container,
+    },
+    /// Written to a headerless raw video file, frame after frame, without encoding
+    Raw {
+        /// Path of the file
+        path: PathBuf,
+        /// Pixel format of the frames
+        format: RawFormat,
+    },
+    /// Written as an HLS playlist and its segments
+    Hls(SegmentOptions),
+    /// Written as a DASH manifest and its segments
+    Dash(SegmentOptions),
+}
+
+impl Output {
+    /// Whether the output takes the raw video: raw files, images, animations, or segments
+    /// encoded once per rendition
+    pub fn takes_raw_video(&self) -> bool {
+        match self {
+            Output::Hls(seg) | Output::Dash(seg) => !seg.renditions.is_empty(),
+            Output::Frames(_) | Output::Animation(_) | Output::Raw { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Directory the output writes its files to, if it writes any; it may not exist yet
+    pub fn dir(&self) -> Option<&Path> {
+        let path = match self {
+            Output::Rtp { sdp, .. } => sdp.as_deref()?,
+            Output::File { path, .. }
+            | Output::EventRecording { path, .. }
+            | Output::Raw { path, .. } => path,
+            Output::Frames(frames) => &frames.pattern,
+            Output::Animation(animation) => &animation.path,
+            Output::Hls(seg) | Output::Dash(seg) => &seg.manifest,
+            Output::Screen | Output::Rtsp { .. } | Output::Stdout { .. } => return None,
+        };
+        Some(
+            path.parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        )
+    }
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Screen => write!(f, "screen"),
+            Output::Rtp { host, port, .. } => write!(f, "rtp://{}:{}", host, port),
+            Output::Rtsp { port, mount } => write!(f, "rtsp://0.0.0.0:{}{}", port, mount),
+            Output::File { path, .. } => write!(f, "{}", path.display()),
+            Output::EventRecording { path, .. } => write!(f, "event recordings {}", path.display()),
+            Output::Frames(frames) => write!(f, "frames {}", frames.pattern.display()),
+            Output::Animation(anim) => write!(f, "{} {}", anim.format, anim.path.display()),
+            Output::Raw { path, format } => write!(f, "raw {} {}", format, path.display()),
+            Output::Stdout { .. } => write!(f, "stdout"),
+            Output::Hls(seg) => write!(f, "HLS {}", seg.manifest.display()),
+            Output::Dash(seg) => write!(f, "DASH {}", seg.manifest.display()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// An output and how the video is encoded for it
+pub struct OutputOptions {
+    /// Where the video goes
+    pub target: Output,
+    /// Resolution (width x height) of this output, if different from the processed video's
+    pub width_height: Option<(i32, i32)>,
+    /// Encoding of this output, if different from --format
+    pub format: Option<VideoFormat>,
+}
+
+impl Display for OutputOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.target)?;
+        if let Some((w, h)) = self.width_height {
+            write!(f, " at {}x{}", w, h)?;
+        }
+        if let Some(format) = self.format {
+            write!(f, " as {}", format)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Pixel formats of headerless raw video files
+pub enum RawFormat {
+    /// Planar 4:2:0 YUV
+    I420,
+    /// 4:2:0 YUV with interleaved UV plane
+    Nv12,
+    /// Packed 4:2:2 YUV
+    Yuy2,
+    /// Packed 24-bit RGB
+    Rgb,
+    /// Packed 24-bit BGR
+    Bgr,
+    /// Packed 32-bit RGBA
+    Rgba,
+}
+
+impl Display for RawFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawFormat::I420 => write!(f, "i420"),
+            RawFormat::Nv12 => write!(f, "nv12"),
+            RawFormat::Yuy2 => write!(f, "yuy2"),
+            RawFormat::Rgb => write!(f, "rgb"),
+            RawFormat::Bgr => write!(f, "bgr"),
+            RawFormat::Rgba => write!(f, "rgba"),
+        }
+    }
+}
+
+impl RawFormat {
+    /// Bytes per row and number of rows of each plane of a `width` x `height` frame, packed
+    /// without the row padding gstreamer lays frames out with by default
+    pub fn packed_planes(&self, width: i32, height: i32) -> Vec<(i32, i32)> {
+        // Subsampled chroma covers odd sizes with one more sample
+        let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+        match self {
+            RawFormat::I420 => vec![
+                (width, height),
+                (chroma_width, chroma_height),
+                (chroma_width, chroma_height),
+            ],
+            RawFormat::Nv12 => vec![(width, height), (2 * chroma_width, chroma_height)],
+            RawFormat::Yuy2 => vec![(4 * chroma_width, height)],
+            RawFormat::Rgb | RawFormat::Bgr => vec![(3 * width, height)],
+            RawFormat::Rgba => vec![(4 * width, height)],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// How a file leads into the next one, when several are played in a row
+pub enum TransitionKind {
+    /// Straight from the last frame of one file to the first frame of the next
+    Cut,
+    /// The next file fades in over the end of the previous one, and so does its audio
+    Crossfade,
+    /// The picture fades to black and the sound to silence, then the next file fades in
+    DipToBlack,
+    /// The next file wipes over the end of the previous one, from left to right, while the
+    /// audio crossfades
+    Wipe,
+}
+
+impl Display for TransitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionKind::Cut => write!(f, "cut"),
+            TransitionKind::Crossfade => write!(f, "crossfade"),
+            TransitionKind::DipToBlack => write!(f, "dip-to-black"),
+            TransitionKind::Wipe => write!(f, "wipe"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Transition between two files played in a row
+pub struct Transition {
+    /// What the transition looks like
+    pub kind: TransitionKind,
+    /// How long the transition lasts, in seconds; crossfades and wipes shorten the video by
+    /// as much, since the files overlap
+    pub duration_secs: f64,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Transition {
+            kind: TransitionKind::Cut,
+            duration_secs: 0.0,
+        }
+    }
+}
+
+impl Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TransitionKind::Cut => write!(f, "{}", self.kind),
+            _ => write!(f, "{} of {:.3}s", self.kind, self.duration_secs),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+/// Corner of the picture
+pub enum Corner {
+    /// Top left
+    TopLeft,
+    /// Top right
+    TopRight,
+    /// Bottom left
+    BottomLeft,
+    /// Bottom right
+    #[default]
+    BottomRight,
+}
+
+impl Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corner::TopLeft => write!(f, "top-left"),
+            Corner::TopRight => write!(f, "top-right"),
+            Corner::BottomLeft => write!(f, "bottom-left"),
+            Corner::BottomRight => write!(f, "bottom-right"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Formats a capture device can be asked to deliver
+pub enum PixelFormat {
+    /// Uncompressed frames
+    Raw,
+    /// Motion JPEG, decoded by the pipeline
+    Mjpeg,
+    /// h264, encoded by the camera and decoded by the pipeline
+    H264,
+}
+
+impl Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PixelFormat::Raw => write!(f, "raw"),
+            PixelFormat::Mjpeg => write!(f, "mjpeg"),
+            PixelFormat::H264 => write!(f, "h264"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Image formats the pipeline graph can be rendered to, besides the DOT source
+pub enum GraphFormat {
+    /// Scalable vector graphics
+    Svg,
+    /// Portable network graphics
+    Png,
+}
+
+impl Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphFormat::Svg => write!(f, "svg"),
+            GraphFormat::Png => write!(f, "png"),
+        }
+    }
+}
+
 #[derive(Debug)]
 /// Errors that can occur while parsing the cmdline arguments
 pub enum Error {
@@ -48,13 +612,232 @@ impl Display for Error {
 
 impl error::Error for Error {}
 
+#[derive(Subcommand, Debug)]
+/// Commands other than playing the processed video
+pub enum Command {
+    /// List the video capture devices and the modes (formats, resolutions, framerates) they
+    /// support, to pick valid --device/--source-width/--source-height/--framerate combinations
+    Devices,
+    /// Publish the processed video with a built-in RTSP server, at rtsp://0.0.0.0:PORT/MOUNT.
+    /// All clients share the same pipeline, so e.g. a webcam is only opened once
+    ServeRtsp {
+        #[arg(long, default_value_t = 8554)]
+        /// Port the RTSP server listens on
+        port: u16,
+        #[arg(long, default_value = "/stream", value_parser = validator::parse_mount)]
+        /// Path the stream is published at
+        mount: String,
+    },
+    /// Write frames of the processed video to PNG or JPEG files, instead of playing it:
+    /// every frame, every Nth frame, every N seconds, at given timestamps or only the keyframes
+    #[command(alias = "snapshot", group(ArgGroup::new("selection").multiple(false)))]
+    ExtractFrames {
+        #[arg(long, value_parser = validator::parse_frame_pattern)]
+        /// File name pattern of the images (.png, .jpg): %d (or %05d, ...) is replaced by the
+        /// frame number, %t by the timestamp in milliseconds, e.g. frames/frame_%05d.png
+        pattern: PathBuf,
+        #[arg(long, group = "selection", value_parser = clap::value_parser!(u64).range(1..))]
+        /// Extract every EVERY-th frame
+        every: Option<u64>,
+        #[arg(long, group = "selection", value_parser = validator::parse_seconds)]
+        /// Extract a frame every INTERVAL seconds
+        interval: Option<f64>,
+        #[arg(long, group = "selection", value_delimiter = ',', value_parser = validator::parse_seconds)]
+        /// Extract the frames at these timestamps, in seconds (e.g. 1.5,10,62)
+        at: Vec<f64>,
+        #[arg(long, group = "selection")]
+        /// Extract the keyframes only
+        keyframes: bool,
+    },
+    /// Write a contact sheet of the input file instead of playing it: a grid of evenly spaced
+    /// frames in one PNG or JPEG image, for a quick look at a long recording
+    ContactSheet {
+        #[arg(long, value_parser = validator::parse_image)]
+        /// Path of the image (.png, .jpg)
+        image: PathBuf,
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+        /// Number of tiles per row
+        columns: u32,
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+        /// Number of rows
+        rows: u32,
+        #[arg(long, default_value_t = 320, value_parser = clap::value_parser!(u32).range(16..))]
+        /// Width of the tiles, in pixels
+        tile_width: u32,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(16..))]
+        /// Height of the tiles, in pixels; follows the aspect ratio of the video if missing
+        tile_height: Option<u32>,
+        #[arg(long)]
+        /// Print the timestamp of each frame under its tile
+        timestamps: bool,
+    },
+}
+
+impl Command {
+    /// The contact sheet options, if the command is `contact-sheet`
+    pub fn contact_sheet(&self) -> Option<ContactSheetOptions> {
+        match self {
+            Command::ContactSheet {
+                image,
+                columns,
+                rows,
+                tile_width,
+                tile_height,
+                timestamps,
+            } => Some(ContactSheetOptions {
+                image: image.clone(),
+                format: ImageFormat::from_path(image).unwrap_or(ImageFormat::Png),
+                columns: *columns,
+                rows: *rows,
+                tile_width: *tile_width,
+                tile_height: *tile_height,
+                timestamps: *timestamps,
+            }),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "harman-challenge")]
 /// Command line arguments definition
 pub struct Cli {
+    #[command(subcommand)]
+    /// What to do instead of processing a video, if anything
+    pub command: Option<Command>,
     #[arg(long, value_parser = validator::parse_fname)]
-    /// Input video file
-    pub input: Option<PathBuf>,
+    /// Input video file, numbered image files (e.g. frame_%04d.png), network stream URI
+    /// (http://, https://, rtsp://, udp://, rtp://, srt://) or - for stdin (MPEG-TS, Matroska).
+    /// Repeat it, or give a playlist of files (*.m3u, *.m3u8, *.txt), to play several files one
+    /// after another into one continuous video
+    pub input: Vec<Input>,
+    #[arg(long, value_name = "KIND[:SECONDS]", value_parser = validator::parse_transition, requires = "input")]
+    /// Transition between files played in a row: cut, crossfade, dip-to-black or wipe, lasting
+    /// SECONDS (1 by default). Given once, it applies to every join; otherwise repeat it once
+    /// per join, in order
+    transition: Vec<Transition>,
+    #[arg(long, value_name = "MS")]
+    /// Latency (jitter buffer size) for network input, in milliseconds
+    latency: Option<u32>,
+    #[arg(long, value_name = "ATTEMPTS", default_value_t = 0)]
+    /// How many times in a row to reconnect to a network input after an error; the count
+    /// starts over once the stream plays again
+    reconnect: u32,
+    #[arg(long, value_name = "SECONDS", default_value_t = 1)]
+    /// How long to wait before reconnecting to a network input
+    reconnect_delay: u64,
+    #[arg(long, value_parser = validator::parse_test_pattern, conflicts_with = "input")]
+    /// Use a synthetic test pattern as input instead of a file or the webcam
+    /// (e.g. smpte, ball, snow, checkers-8)
+    pub test_pattern: Option<String>,
+    #[arg(long, requires = "test_pattern")]
+    /// Number of test pattern frames to produce before stopping; endless if missing
+    num_frames: Option<i32>,
+    #[arg(long, value_parser = validator::parse_device, conflicts_with_all = ["input", "test_pattern"])]
+    /// Capture device to use for webcam input (e.g. /dev/video2); defaults to the first one
+    device: Option<PathBuf>,
+    #[arg(long, requires = "source_height")]
+    /// Width requested from the capture device or test pattern, or of the raw input
+    source_width: Option<i32>,
+    #[arg(long, requires = "source_width")]
+    /// Height requested from the capture device or test pattern, or of the raw input
+    source_height: Option<i32>,
+    #[arg(long)]
+    /// Framerate (frames per second) requested from the capture device or test pattern, of an
+    /// image sequence or raw input (25 by default), or of several concatenated inputs (the
+    /// first one's by default)
+    framerate: Option<i32>,
+    #[arg(long, value_parser = validator::parse_raw_format, requires_all = ["input", "source_width", "source_height"])]
+    /// Read the input file as headerless raw video in this pixel format (i420, nv12, yuy2, rgb,
+    /// bgr, rgba), of --source-width x --source-height at --framerate
+    pub raw_format: Option<RawFormat>,
+    #[arg(long, requires = "output", value_parser = validator::parse_stream_container)]
+    /// Container of the stdout output (ts, mkv); defaults to ts
+    stdout_container: Option<Container>,
+    #[arg(long, requires = "output", value_parser = validator::parse_raw_format)]
+    /// Pixel format of the raw video outputs; defaults to i420 for *.yuv, rgb for *.rgb
+    raw_output_format: Option<RawFormat>,
+    #[arg(long, value_parser = validator::parse_pixel_format, conflicts_with_all = ["input", "test_pattern"])]
+    /// Format requested from the capture device (raw, mjpeg, h264)
+    pixel_format: Option<PixelFormat>,
+    #[arg(long, value_name = "DEVICE", num_args = 0..=1, value_parser = validator::parse_device)]
+    /// Overlay a webcam (DEVICE, or the first one) as a picture-in-picture inset over the input
+    pip: Option<Option<PathBuf>>,
+    #[arg(long, requires = "pip", value_parser = validator::parse_corner)]
+    /// Corner of the inset (top-left, top-right, bottom-left, bottom-right); defaults to
+    /// bottom-right
+    pip_position: Option<Corner>,
+    #[arg(long, requires = "pip", value_parser = validator::parse_resolution)]
+    /// Size of the inset, border excluded (e.g. 320x180, the default)
+    pip_size: Option<(i32, i32)>,
+    #[arg(long, requires = "pip", default_value_t = 16)]
+    /// Space between the inset and the edges of the picture, in pixels
+    pip_margin: u32,
+    #[arg(long, requires = "pip", default_value_t = 0)]
+    /// Width of the white border around the inset, in pixels
+    pip_border: u32,
+    #[arg(long, requires = "pip", value_parser = validator::parse_opacity)]
+    /// Opacity of the inset, from 0 (invisible) to 1 (opaque, the default)
+    pip_opacity: Option<f64>,
+    #[arg(long, requires = "input", conflicts_with_all = ["transition", "pip"])]
+    /// Tile the inputs (files, capture devices, test:// patterns, network streams) into a
+    /// grid of --width x --height (1280x720 by default), instead of playing them one after
+    /// another
+    mosaic: bool,
+    #[arg(long, requires = "mosaic", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Number of columns of the grid; enough for a square grid by default
+    mosaic_columns: Option<u32>,
+    #[arg(long, requires = "mosaic", value_name = "TEXT")]
+    /// Label of a tile, in the order of the inputs; the name of the input by default, none if
+    /// empty
+    pub mosaic_label: Vec<String>,
+    #[arg(long, value_parser = validator::parse_output, value_name = "OUTPUT[,width=W,height=H][,format=F]")]
+    /// Where to send the processed video: "screen" (default), rtp://host:port, a file
+    /// (*.mp4, *.mov, *.mkv, *.ts), an HLS playlist (*.m3u8), a DASH manifest (*.mpd), an
+    /// animated preview (*.gif, *.webp), raw frames (*.yuv, *.rgb) or - for stdout (MPEG-TS).
+    /// Can be repeated to fan out to several outputs, each at its own resolution and format
+    output: Vec<OutputOptions>,
+    #[arg(long, requires = "output")]
+    /// Write an SDP file describing the RTP output, for players to open
+    sdp: Option<PathBuf>,
+    #[arg(long, requires = "output", value_parser = clap::value_parser!(u64).range(1..))]
+    /// Split file outputs into a new file every SPLIT_TIME seconds, on keyframes
+    split_time: Option<u64>,
+    #[arg(long, requires = "output", value_parser = clap::value_parser!(u64).range(1..))]
+    /// Split file outputs into a new file every SPLIT_SIZE megabytes, on keyframes
+    split_size: Option<u64>,
+    #[arg(long, requires = "output", conflicts_with_all = ["split_time", "split_size"])]
+    /// Turn file outputs into event recordings: keep the last PRE_EVENT seconds of encoded video
+    /// in memory and write them, plus the following --post-event seconds, to a new file on each
+    /// trigger (SIGUSR1, or "trigger" on stdin)
+    pre_event: Option<u64>,
+    #[arg(long, requires = "pre_event", default_value_t = 10)]
+    /// Seconds recorded after each event trigger
+    post_event: u64,
+    #[arg(long, requires = "output", value_parser = validator::parse_segment_format)]
+    /// Container of the HLS/DASH segments (ts, fmp4); defaults to ts for HLS, fmp4 for DASH
+    segment_format: Option<SegmentFormat>,
+    #[arg(long, requires = "output", default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Target duration of the HLS/DASH segments, in seconds
+    segment_duration: u32,
+    #[arg(long, requires = "output", default_value_t = 5)]
+    /// Number of segments listed in the HLS playlist; 0 keeps all of them
+    playlist_length: u32,
+    #[arg(long, requires = "output", value_delimiter = ',', value_parser = validator::parse_resolution)]
+    /// Encode the HLS/DASH output at several resolutions from one decode (e.g. 1280x720,640x360)
+    renditions: Vec<(i32, i32)>,
+    #[arg(long, requires = "output", default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Frame rate of the GIF/WebP outputs
+    animation_fps: u32,
+    #[arg(long, requires = "output", default_value_t = 480)]
+    /// Maximum width of the GIF/WebP outputs, which are scaled down if wider; 0 for no limit
+    animation_max_width: u32,
+    #[arg(long, requires = "input", value_parser = validator::parse_seconds)]
+    /// Start processing the input file at START seconds
+    start: Option<f64>,
+    #[arg(long, requires = "input", value_parser = validator::parse_seconds)]
+    /// Stop processing the input file after DURATION seconds
+    duration: Option<f64>,
     #[arg(long, value_parser = validator::parse_format)]
     /// Output video format
     format: Option<VideoFormat>,
@@ -70,9 +853,328 @@ pub struct Cli {
     #[arg(long)]
     /// Flag that specifies whether the output file should be flipped horizontally
     flip: bool,
+    #[arg(long, value_name = "DESCRIPTION")]
+    /// Custom filter as a gst-launch-1.0 fragment (e.g. "videobalance saturation=0"), applied after
+    /// the other filters and before encoding. Can be repeated, fragments are applied in order
+    filter_bin: Vec<String>,
+    #[arg(long, value_parser = validator::parse_dir)]
+    /// Directory where DOT graphs of the pipeline are written at each state change and on error
+    dump_graph: Option<PathBuf>,
+    #[arg(long, value_parser = validator::parse_graph_format, requires = "dump_graph")]
+    /// Also render the DOT graphs to this format (svg, png), if graphviz's `dot` is installed
+    graph_format: Option<GraphFormat>,
+    #[arg(long)]
+    /// Print the equivalent gst-launch-1.0 command line instead of playing the video
+    pub dry_run: bool,
+    #[arg(short, long, action = ArgAction::Count)]
+    /// Increase logging verbosity (-v: info, -vv: debug, -vvv: trace)
+    verbose: u8,
+    #[arg(short, long, action = ArgAction::Count)]
+    /// Decrease logging verbosity (-q: errors only, -qq: silent)
+    quiet: u8,
+    #[arg(long)]
+    /// Log level (off, error, warn, info, debug, trace); overrides -v/-q.
+    /// Also applies to gstreamer's own debug output
+    log_level: Option<LevelFilter>,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Cli {
+    /// Split options of file outputs, if any of the splitting flags is given
+    fn split_options(&self) -> Option<SplitOptions> {
+        if self.split_time.is_none() && self.split_size.is_none() {
+            return None;
+        }
+        Some(SplitOptions {
+            max_time_secs: self.split_time,
+            max_size_mb: self.split_size,
+        })
+    }
+
+    /// Segmented output options for `manifest`, from the segmenting flags
+    fn segment_options(&self, manifest: PathBuf) -> SegmentOptions {
+        SegmentOptions {
+            manifest,
+            segment_format: self.segment_format,
+            segment_duration: self.segment_duration,
+            playlist_length: self.playlist_length,
+            renditions: self.renditions.clone(),
+        }
+    }
+
+    /// The picture-in-picture options, if a webcam inset is requested with `--pip`
+    pub fn pip_options(&self) -> Option<PipOptions> {
+        let device = self.pip.as_ref()?;
+        Some(PipOptions {
+            webcam: WebcamOptions {
+                device: device.as_ref().map(|d| d.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            canvas: self.width.zip(self.height),
+            position: self.pip_position.unwrap_or_default(),
+            size: self.pip_size.unwrap_or((320, 180)),
+            margin: self.pip_margin as i32,
+            border: self.pip_border as i32,
+            opacity: self.pip_opacity.unwrap_or(1.0),
+        })
+    }
+
+    /// Mosaic options, if the inputs are tiled into a grid; tiles without a `--mosaic-label`
+    /// are labeled with the name of their input
+    pub fn mosaic_options(&self) -> Option<MosaicOptions> {
+        if !self.mosaic {
+            return None;
+        }
+        Some(MosaicOptions {
+            canvas: self.width.zip(self.height).unwrap_or((1280, 720)),
+            columns: self.mosaic_columns,
+            labels: self
+                .inputs()
+                .iter()
+                .enumerate()
+                .map(|(i, input)| {
+                    self.mosaic_label
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| input.label())
+                })
+                .collect(),
+        })
+    }
+
+    /// The inputs, in playing order, with the playlists replaced by the files they list
+    pub fn inputs(&self) -> Vec<Input> {
+        self.input
+            .iter()
+            .flat_map(|input| match input {
+                Input::Playlist(files) => files.iter().cloned().map(Input::File).collect(),
+                other => vec![other.clone()],
+            })
+            .collect()
+    }
+
+    /// Log level resulting from `--log-level`, or from the `-v`/`-q` flags applied on top
+    /// of the default level (`warn`)
+    pub fn log_level(&self) -> LevelFilter {
+        if let Some(level) = self.log_level {
+            return level;
+        }
+        const LEVELS: [LevelFilter; 6] = [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ];
+        let idx = (2 + self.verbose as i32 - self.quiet as i32).clamp(0, 5);
+        LEVELS[idx as usize]
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Webcam capture options. Whatever is left unset is negotiated with the device
+pub struct WebcamOptions {
+    /// Capture device path; the default device if unset
+    pub device: Option<String>,
+    /// Capture resolution (width x height)
+    pub width_height: Option<(i32, i32)>,
+    /// Capture framerate, in frames per second
+    pub framerate: Option<i32>,
+    /// Format delivered by the device
+    pub pixel_format: Option<PixelFormat>,
+}
+
+impl From<&Cli> for WebcamOptions {
+    fn from(cli: &Cli) -> Self {
+        let mut opts = WebcamOptions {
+            device: cli.device.as_ref().map(|d| d.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        if let Some(w) = cli.source_width {
+            if let Some(h) = cli.source_height {
+                opts.width_height = Some((w, h));
+            }
+        }
+        opts.framerate = cli.framerate;
+        opts.pixel_format = cli.pixel_format;
+        opts
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Network input options
+pub struct NetworkOptions {
+    /// Latency (jitter buffer size), in milliseconds; the source's default if unset
+    pub latency_ms: Option<u32>,
+    /// How many times in a row to reconnect after an error
+    pub reconnect_attempts: u32,
+    /// How long to wait before reconnecting, in seconds
+    pub reconnect_delay_secs: u64,
+}
+
+impl From<&Cli> for NetworkOptions {
+    fn from(cli: &Cli) -> Self {
+        NetworkOptions {
+            latency_ms: cli.latency,
+            reconnect_attempts: cli.reconnect,
+            reconnect_delay_secs: cli.reconnect_delay,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Raw video input options: what the headerless file holds
+pub struct RawOptions {
+    /// Pixel format of the frames
+    pub format: RawFormat,
+    /// Resolution of the frames (width x height)
+    pub width_height: (i32, i32),
+    /// Framerate of the video, in frames per second
+    pub framerate: i32,
+}
+
+impl From<&Cli> for RawOptions {
+    fn from(cli: &Cli) -> Self {
+        RawOptions {
+            format: cli.raw_format.unwrap_or(RawFormat::I420),
+            width_height: (
+                cli.source_width.unwrap_or_default(),
+                cli.source_height.unwrap_or_default(),
+            ),
+            framerate: cli.framerate.unwrap_or(25),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Options of several files played one after another
+pub struct ConcatOptions {
+    /// Framerate all the files are converted to, in frames per second; the first file's if
+    /// unset
+    pub framerate: Option<i32>,
+    /// Transitions between the files: one for all the joins, or one per join
+    pub transitions: Vec<Transition>,
+}
+
+impl ConcatOptions {
+    /// The transition from the `n`th file (from 0) to the next one
+    pub fn transition(&self, n: usize) -> Transition {
+        match self.transitions.as_slice() {
+            [all] => *all,
+            transitions => transitions.get(n).copied().unwrap_or_default(),
+        }
+    }
+
+    /// Whether any of the joins is more than a cut
+    pub fn has_transitions(&self) -> bool {
+        self.transitions
+            .iter()
+            .any(|transition| transition.kind != TransitionKind::Cut)
+    }
+}
+
+impl From<&Cli> for ConcatOptions {
+    fn from(cli: &Cli) -> Self {
+        ConcatOptions {
+            framerate: cli.framerate,
+            transitions: cli.transition.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Picture-in-picture options: a webcam drawn as an inset over the main input
+pub struct PipOptions {
+    /// Webcam shown in the inset
+    pub webcam: WebcamOptions,
+    /// Size of the picture; the main input's if unset
+    pub canvas: Option<(i32, i32)>,
+    /// Corner of the picture the inset is placed in
+    pub position: Corner,
+    /// Size of the inset, border excluded
+    pub size: (i32, i32),
+    /// Space between the inset (border included) and the edges of the picture, in pixels
+    pub margin: i32,
+    /// Width of the white border around the inset, in pixels
+    pub border: i32,
+    /// Opacity of the inset, from 0 (invisible) to 1 (opaque)
+    pub opacity: f64,
+}
+
+#[derive(Clone, Debug)]
+/// Mosaic options: several inputs tiled into a grid
+pub struct MosaicOptions {
+    /// Size of the picture (width x height)
+    pub canvas: (i32, i32),
+    /// Number of columns of the grid; enough for a square grid if unset
+    pub columns: Option<u32>,
+    /// Label of each tile, in order; empty for no label
+    pub labels: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+/// Image sequence input options
+pub struct ImageSequenceOptions {
+    /// Number of the first file
+    pub start_index: u32,
+    /// Framerate of the video, in frames per second
+    pub framerate: i32,
+}
+
+impl From<&Cli> for ImageSequenceOptions {
+    fn from(cli: &Cli) -> Self {
+        ImageSequenceOptions {
+            start_index: match cli.input.first() {
+                Some(Input::ImageSequence { start_index, .. }) => *start_index,
+                _ => 0,
+            },
+            framerate: cli.framerate.unwrap_or(25),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Synthetic test pattern options
+pub struct TestPatternOptions {
+    /// Pattern name, as known to `videotestsrc` (e.g. `smpte`, `ball`)
+    pub pattern: String,
+    /// Resolution (width x height)
+    pub width_height: Option<(i32, i32)>,
+    /// Framerate, in frames per second
+    pub framerate: Option<i32>,
+    /// Number of frames to produce; endless if unset
+    pub num_frames: Option<i32>,
+}
+
+impl Default for TestPatternOptions {
+    fn default() -> Self {
+        Self {
+            pattern: "smpte".to_string(),
+            width_height: None,
+            framerate: None,
+            num_frames: None,
+        }
+    }
+}
+
+impl From<&Cli> for TestPatternOptions {
+    fn from(cli: &Cli) -> Self {
+        let mut opts = TestPatternOptions::default();
+        if let Some(pattern) = &cli.test_pattern {
+            opts.pattern = pattern.clone();
+        }
+        if let Some(w) = cli.source_width {
+            if let Some(h) = cli.source_height {
+                opts.width_height = Some((w, h));
+            }
+        }
+        opts.framerate = cli.framerate;
+        opts.num_frames = cli.num_frames;
+        opts
+    }
+}
+
+#[derive(Clone, Debug)]
 /// Video manipulator options
 pub struct DecoderOptions {
     /// Output resolution (width x height)
@@ -82,6 +1184,18 @@ pub struct DecoderOptions {
     /// Flag that specifies whether the output file should be flipped horizontally
     pub flip: bool,
     pub format: VideoFormat,
+    /// Where the processed video goes, one branch of the pipeline each
+    pub outputs: Vec<OutputOptions>,
+    /// Custom filters, as `gst-launch-1.0` fragments
+    pub filter_bins: Vec<String>,
+    /// Directory where DOT graphs of the pipeline are dumped, if any
+    pub dump_graph: Option<PathBuf>,
+    /// Format the DOT graphs are rendered to, if any
+    pub graph_format: Option<GraphFormat>,
+    /// Where to start in the input, in seconds, if not at the beginning
+    pub start_secs: Option<f64>,
+    /// How much of the input to process, in seconds, if not all of it
+    pub duration_secs: Option<f64>,
 }
 
 impl Default for DecoderOptions {
@@ -91,6 +1205,12 @@ impl Default for DecoderOptions {
             invert: false,
             flip: false,
             format: VideoFormat::H264,
+            outputs: vec![OutputOptions::default()],
+            filter_bins: vec![],
+            dump_graph: None,
+            graph_format: None,
+            start_secs: None,
+            duration_secs: None,
         }
     }
 }
@@ -106,6 +1226,230 @@ impl From<&Cli> for DecoderOptions {
         opts.invert = cli.invert;
         opts.flip = cli.flip;
         opts.format = cli.format.unwrap_or(VideoFormat::H264);
+        opts.start_secs = cli.start;
+        opts.duration_secs = cli.duration;
+        if !cli.output.is_empty() {
+            opts.outputs = cli
+                .output
+                .iter()
+                .cloned()
+                .map(|out| OutputOptions {
+                    target: match out.target {
+                        Output::Rtp { host, port, .. } => Output::Rtp {
+                            host,
+                            port,
+                            sdp: cli.sdp.clone(),
+                        },
+                        Output::File {
+                            path, container, ..
+                        } => match cli.pre_event {
+                            Some(pre_secs) => Output::EventRecording {
+                                path,
+                                container,
+                                pre_secs,
+                                post_secs: cli.post_event,
+                            },
+                            None => Output::File {
+                                path,
+                                container,
+                                split: cli.split_options(),
+                            },
+                        },
+                        Output::Hls(seg) => Output::Hls(cli.segment_options(seg.manifest)),
+                        Output::Dash(seg) => Output::Dash(cli.segment_options(seg.manifest)),
+                        Output::Stdout { container } => Output::Stdout {
+                            container: cli.stdout_container.unwrap_or(container),
+                        },
+                        Output::Raw { path, format } => Output::Raw {
+                            path,
+                            format: cli.raw_output_format.unwrap_or(format),
+                        },
+                        Output::Animation(anim) => Output::Animation(AnimationOptions {
+                            fps: cli.animation_fps,
+                            max_width: Some(cli.animation_max_width).filter(|w| *w > 0),
+                            ..anim
+                        }),
+                        target => target,
+                    },
+                    ..out
+                })
+                .collect();
+        }
+        if let Some(Command::ExtractFrames {
+            pattern,
+            every,
+            interval,
+            at,
+            keyframes,
+        }) = &cli.command
+        {
+            if !cli.output.is_empty() {
+                warn!("extract-frames ignores --output");
+            }
+            let selection = match (every, interval) {
+                (Some(n), _) => FrameSelection::Every(*n),
+                (_, Some(secs)) => FrameSelection::Interval(*secs),
+                _ if !at.is_empty() => FrameSelection::Timestamps(at.clone()),
+                _ if *keyframes => FrameSelection::Keyframes,
+                _ => FrameSelection::Every(1),
+            };
+            opts.outputs = vec![OutputOptions {
+                target: Output::Frames(FrameOptions {
+                    pattern: pattern.clone(),
+                    format: ImageFormat::from_path(pattern).unwrap_or(ImageFormat::Png),
+                    selection,
+                }),
+                ..Default::default()
+            }];
+        }
+        if let Some(Command::ServeRtsp { port, mount }) = &cli.command {
+            if !cli.output.is_empty() {
+                warn!("serve-rtsp ignores --output");
+            }
+            opts.outputs = vec![OutputOptions {
+                target: Output::Rtsp {
+                    port: *port,
+                    mount: mount.clone(),
+                },
+                ..Default::default()
+            }];
+        }
+        opts.filter_bins = cli.filter_bin.clone();
+        opts.dump_graph = cli.dump_graph.clone();
+        opts.graph_format = cli.graph_format;
+        debug!("Decoder options: {:?}", opts);
         opts
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["harman-challenge"].iter().chain(args)).unwrap()
+    }
+
+    /// Options joining files with `transitions`, each given as to --transition
+    fn concat(transitions: &[&str]) -> ConcatOptions {
+        ConcatOptions {
+            framerate: None,
+            transitions: transitions
+                .iter()
+                .map(|transition| validator::parse_transition(transition).unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn frame_patterns_expand_numbers_and_timestamps() {
+        assert_eq!(expand_frame_pattern("frame_%d.png", 7, 0), "frame_7.png");
+        assert_eq!(
+            expand_frame_pattern("frame_%05d.png", 42, 0),
+            "frame_00042.png"
+        );
+        assert_eq!(expand_frame_pattern("at_%t.jpg", 3, 1500), "at_1500.jpg");
+        assert_eq!(expand_frame_pattern("%04d_%t.png", 12, 480), "0012_480.png");
+    }
+
+    #[test]
+    fn frame_patterns_keep_literal_percent_signs() {
+        assert_eq!(expand_frame_pattern("100%%_%d.png", 1, 0), "100%_1.png");
+        assert_eq!(expand_frame_pattern("50%x.png", 1, 0), "50%x.png");
+        assert_eq!(expand_frame_pattern("%3t.png", 1, 2), "%3t.png");
+        assert_eq!(expand_frame_pattern("frame.png", 1, 2), "frame.png");
+        assert_eq!(expand_frame_pattern("end%", 1, 2), "end%");
+    }
+
+    #[test]
+    fn packed_planes_have_no_row_padding() {
+        assert_eq!(
+            RawFormat::I420.packed_planes(176, 144),
+            vec![(176, 144), (88, 72), (88, 72)]
+        );
+        assert_eq!(
+            RawFormat::Nv12.packed_planes(176, 144),
+            vec![(176, 144), (176, 72)]
+        );
+        assert_eq!(RawFormat::Yuy2.packed_planes(176, 144), vec![(352, 144)]);
+        assert_eq!(RawFormat::Rgb.packed_planes(175, 144), vec![(525, 144)]);
+        assert_eq!(RawFormat::Rgba.packed_planes(175, 144), vec![(700, 144)]);
+    }
+
+    #[test]
+    fn packed_planes_round_subsampled_chroma_up() {
+        assert_eq!(
+            RawFormat::I420.packed_planes(175, 143),
+            vec![(175, 143), (88, 72), (88, 72)]
+        );
+        assert_eq!(
+            RawFormat::Nv12.packed_planes(175, 143),
+            vec![(175, 143), (176, 72)]
+        );
+        assert_eq!(RawFormat::Yuy2.packed_planes(175, 143), vec![(352, 143)]);
+    }
+
+    #[test]
+    fn one_transition_applies_to_every_join() {
+        let opts = concat(&["crossfade:2"]);
+        for join in 0..2 {
+            assert_eq!(opts.transition(join).kind, TransitionKind::Crossfade);
+            assert_eq!(opts.transition(join).duration_secs, 2.0);
+        }
+        assert!(opts.has_transitions());
+    }
+
+    #[test]
+    fn missing_transitions_are_cuts() {
+        let opts = concat(&[]);
+        assert_eq!(opts.transition(0).kind, TransitionKind::Cut);
+        assert!(!opts.has_transitions());
+
+        let opts = concat(&["wipe", "cut"]);
+        assert_eq!(opts.transition(0).kind, TransitionKind::Wipe);
+        assert_eq!(opts.transition(1).kind, TransitionKind::Cut);
+        assert_eq!(opts.transition(2).kind, TransitionKind::Cut);
+        assert_eq!(opts.transition(2).duration_secs, 0.0);
+        assert!(opts.has_transitions());
+    }
+
+    #[test]
+    fn source_sizes_need_both_dimensions() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["harman-challenge"].iter().chain(args));
+        assert!(parse(&["--source-width=640"]).is_err());
+        assert!(parse(&["--test-pattern=smpte", "--source-height=480"]).is_err());
+        assert!(parse(&["--source-width=640", "--source-height=480"]).is_ok());
+    }
+
+    #[test]
+    fn log_level_defaults_to_warn() {
+        assert_eq!(cli(&[]).log_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn log_level_follows_verbose_and_quiet() {
+        assert_eq!(cli(&["-v"]).log_level(), LevelFilter::Info);
+        assert_eq!(cli(&["-vv"]).log_level(), LevelFilter::Debug);
+        assert_eq!(cli(&["-q"]).log_level(), LevelFilter::Error);
+        assert_eq!(cli(&["-vv", "-q"]).log_level(), LevelFilter::Info);
+        assert_eq!(cli(&["-v", "-q"]).log_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn log_level_is_clamped() {
+        assert_eq!(cli(&["-vvvvv"]).log_level(), LevelFilter::Trace);
+        assert_eq!(cli(&["-qqqq"]).log_level(), LevelFilter::Off);
+    }
+
+    #[test]
+    fn log_level_overrides_verbose_and_quiet() {
+        assert_eq!(
+            cli(&["-q", "--log-level", "debug"]).log_level(),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            cli(&["-vvv", "--log-level=error"]).log_level(),
+            LevelFilter::Error
+        );
+    }
+}
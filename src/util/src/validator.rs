@@ -1,13 +1,101 @@
 use std::fs;
 use std::io;
-use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 
-use super::Error;
+use super::{
+    AnimationFormat, AnimationOptions, Container, Error, ImageFormat, Input, Output, OutputOptions,
+    RawFormat, SegmentFormat, SegmentOptions,
+};
+
+/// Patterns supported by `videotestsrc`
+const TEST_PATTERNS: [&str; 26] = [
+    "smpte",
+    "snow",
+    "black",
+    "white",
+    "red",
+    "green",
+    "blue",
+    "checkers-1",
+    "checkers-2",
+    "checkers-4",
+    "checkers-8",
+    "circular",
+    "blink",
+    "smpte75",
+    "zone-plate",
+    "gamut",
+    "chroma-zone-plate",
+    "solid-color",
+    "ball",
+    "smpte100",
+    "bar",
+    "pinwheel",
+    "spokes",
+    "gradient",
+    "colors",
+    "smpte-rp-219",
+];
+
+/// URI schemes accepted for network input
+const NETWORK_SCHEMES: [&str; 8] = [
+    "http", "https", "rtsp", "rtsps", "rtspt", "udp", "rtp", "srt",
+];
+
+/// Validates that a network URI has a supported scheme and, for the schemes that
+/// listen locally (udp, rtp), a port
+fn parse_uri(uri: &str) -> Result<Input, Error> {
+    let (scheme, rest) = uri
+        .split_once("://")
+        .ok_or_else(|| Error::Format(uri.to_string()))?;
+    let scheme = scheme.to_ascii_lowercase();
+    if !NETWORK_SCHEMES.contains(&scheme.as_str()) || rest.is_empty() {
+        return Err(Error::Format(format!(
+            "{} (supported URI schemes: {})",
+            uri,
+            NETWORK_SCHEMES.join(", ")
+        )));
+    }
+    if scheme == "udp" || scheme == "rtp" {
+        let port = rest.rsplit_once(':').map(|(_, port)| port);
+        if port.and_then(|p| p.parse::<u16>().ok()).is_none() {
+            return Err(Error::Format(format!("{} (missing port)", uri)));
+        }
+    }
+    Ok(Input::Uri(uri.to_string()))
+}
+
+/// Extensions of the playlist files accepted as input
+const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "txt"];
+
+/// Validates that the input file specified exists and is readable (or, for a pattern of
+/// numbered images, that the first one does, and for a playlist, that all the files it lists
+/// do), that the input is a supported network URI, a capture device, a `test://` pattern known
+/// to `videotestsrc`, or `-` for stdin
+pub fn parse_fname(fnamestr: &str) -> Result<Input, Error> {
+    if fnamestr == "-" {
+        return Ok(Input::Stdin);
+    }
+    if let Some(pattern) = fnamestr.strip_prefix("test://") {
+        return parse_test_pattern(pattern).map(Input::TestPattern);
+    }
+    if let Some(path) = fnamestr.strip_prefix("file://") {
+        return parse_fname(path);
+    }
+    if fnamestr.contains("://") {
+        return parse_uri(fnamestr);
+    }
 
-/// Validates that the input file specifies exists and is readable
-pub fn parse_fname(fnamestr: &str) -> Result<PathBuf, Error> {
     let fname = PathBuf::from(fnamestr);
+    if let Some(name) = fname.file_name().and_then(|name| name.to_str()) {
+        if super::expand_frame_pattern(name, 0, 0) != super::expand_frame_pattern(name, 1, 0) {
+            return parse_image_sequence(&fname, name);
+        }
+    }
+    if fs::metadata(&fname).is_ok_and(|meta| meta.file_type().is_char_device()) {
+        return Ok(Input::Device(fname));
+    }
     if !fname.exists() || !fname.is_file() {
         return Err(Error::Io(io::Error::new(
             io::ErrorKind::NotFound,
@@ -18,7 +106,14 @@ pub fn parse_fname(fnamestr: &str) -> Result<PathBuf, Error> {
     // must be a file and must be readable = O_R**
     let fmeta = fs::metadata(fname.clone()).map_err(Error::Io)?;
     if fmeta.permissions().mode() >= 0o600 {
-        return Ok(fname);
+        let is_playlist = fname
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+        if is_playlist {
+            return parse_playlist(&fname);
+        }
+        return Ok(Input::File(fname));
     }
     Err(Error::Io(io::Error::new(
         io::ErrorKind::PermissionDenied,
@@ -26,6 +121,82 @@ pub fn parse_fname(fnamestr: &str) -> Result<PathBuf, Error> {
     )))
 }
 
+/// Validates a playlist: one file per line, relative to the playlist's directory unless
+/// absolute. Empty lines and lines starting with `#` (such as the `#EXTINF` lines of M3U
+/// playlists) are skipped
+fn parse_playlist(playlist: &Path) -> Result<Input, Error> {
+    let dir = playlist.parent().unwrap_or(Path::new(""));
+    let mut files = vec![];
+    for line in fs::read_to_string(playlist).map_err(Error::Io)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = dir.join(line);
+        match parse_fname(&path.to_string_lossy()) {
+            Ok(Input::File(file)) => files.push(file),
+            Ok(_) => {
+                return Err(Error::Format(format!(
+                    "{} (a playlist can only list video files)",
+                    line
+                )))
+            }
+            Err(Error::Io(e)) => {
+                return Err(Error::Io(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", path.display(), e),
+                )))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if files.is_empty() {
+        return Err(Error::Format(format!(
+            "{} (empty playlist)",
+            playlist.display()
+        )));
+    }
+    Ok(Input::Playlist(files))
+}
+
+/// Validates a pattern of numbered image files: a .png or .jpg file name with a %d-style
+/// placeholder, whose first file (numbered 0 or 1) exists
+fn parse_image_sequence(pattern: &Path, name: &str) -> Result<Input, Error> {
+    if ImageFormat::from_path(pattern).is_none() {
+        return Err(Error::Format(format!(
+            "{} (supported image formats: png, jpg)",
+            pattern.display()
+        )));
+    }
+    let first = |index: u64| pattern.with_file_name(super::expand_frame_pattern(name, index, 0));
+    match (0..=1).find(|index| first(*index).is_file()) {
+        Some(start_index) => Ok(Input::ImageSequence {
+            pattern: pattern.to_path_buf(),
+            start_index: start_index as u32,
+        }),
+        None => Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "Neither {} nor {} found",
+                first(0).display(),
+                first(1).display()
+            ),
+        ))),
+    }
+}
+
+/// Validates that the capture device specified exists
+pub fn parse_device(devstr: &str) -> Result<PathBuf, Error> {
+    let dev = PathBuf::from(devstr);
+    if !dev.exists() {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Device not found",
+        )));
+    }
+    Ok(dev)
+}
+
 /// Validates that the format specified is supported
 /// Currently the only supported format is h264.
 /// Case insensitive
@@ -35,3 +206,667 @@ pub fn parse_format(format: &str) -> Result<super::VideoFormat, Error> {
     }
     Err(super::Error::Format(format.to_string()))
 }
+
+/// Validates that the directory specified exists or can be created: the closest of it and its
+/// parents that exists must be a directory. Nothing is created here, the directory is created
+/// and checked to be writable when the output starts
+pub fn parse_dir(dirstr: &str) -> Result<PathBuf, Error> {
+    let dir = PathBuf::from(dirstr);
+    let existing = dir
+        .ancestors()
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("{} is not a directory", existing.display()),
+        )));
+    }
+    Ok(dir)
+}
+
+/// Validates that the graph format specified is supported (svg or png).
+/// Case insensitive
+pub fn parse_graph_format(format: &str) -> Result<super::GraphFormat, Error> {
+    if format.eq_ignore_ascii_case("svg") {
+        return Ok(super::GraphFormat::Svg);
+    }
+    if format.eq_ignore_ascii_case("png") {
+        return Ok(super::GraphFormat::Png);
+    }
+    Err(super::Error::Format(format.to_string()))
+}
+
+/// Validates that the capture pixel format specified is supported (raw, mjpeg or h264).
+/// Case insensitive
+pub fn parse_pixel_format(format: &str) -> Result<super::PixelFormat, Error> {
+    match format.to_ascii_lowercase().as_str() {
+        "raw" => Ok(super::PixelFormat::Raw),
+        "mjpeg" | "jpeg" => Ok(super::PixelFormat::Mjpeg),
+        "h264" => Ok(super::PixelFormat::H264),
+        _ => Err(super::Error::Format(format.to_string())),
+    }
+}
+
+/// Validates the container of a stream written to stdout: one that can be played while it is
+/// written (ts, mkv)
+pub fn parse_stream_container(container: &str) -> Result<Container, Error> {
+    match container.to_ascii_lowercase().as_str() {
+        "ts" | "mpegts" => Ok(Container::MpegTs),
+        "mkv" | "matroska" => Ok(Container::Matroska),
+        _ => Err(super::Error::Format(format!(
+            "{} (supported stream containers: ts, mkv)",
+            container
+        ))),
+    }
+}
+
+/// Validates a raw video pixel format: i420, nv12, yuy2, rgb, bgr or rgba.
+/// Case insensitive
+pub fn parse_raw_format(format: &str) -> Result<super::RawFormat, Error> {
+    match format.to_ascii_lowercase().as_str() {
+        "i420" | "yuv420p" => Ok(super::RawFormat::I420),
+        "nv12" => Ok(super::RawFormat::Nv12),
+        "yuy2" | "yuyv" => Ok(super::RawFormat::Yuy2),
+        "rgb" | "rgb24" => Ok(super::RawFormat::Rgb),
+        "bgr" | "bgr24" => Ok(super::RawFormat::Bgr),
+        "rgba" => Ok(super::RawFormat::Rgba),
+        _ => Err(super::Error::Format(format!(
+            "{} (supported raw formats: i420, nv12, yuy2, rgb, bgr, rgba)",
+            format
+        ))),
+    }
+}
+
+/// Validates that the test pattern specified is known to `videotestsrc`.
+/// Case insensitive
+pub fn parse_test_pattern(pattern: &str) -> Result<String, Error> {
+    let pattern = pattern.to_ascii_lowercase();
+    if TEST_PATTERNS.contains(&pattern.as_str()) {
+        return Ok(pattern);
+    }
+    Err(super::Error::Format(format!(
+        "{} (supported test patterns: {})",
+        pattern,
+        TEST_PATTERNS.join(", ")
+    )))
+}
+
+/// Validates an output target: "screen", an rtp://host:port destination, or a file (.mp4, .mov,
+/// .mkv, .ts), HLS playlist (.m3u8) or DASH manifest (.mpd) in a writable directory, or one
+/// that can be created when the output starts
+fn parse_target(output: &str) -> Result<Output, Error> {
+    if output.eq_ignore_ascii_case("screen") {
+        return Ok(Output::Screen);
+    }
+    if output == "-" {
+        return Ok(Output::Stdout {
+            container: Container::MpegTs,
+        });
+    }
+    let path = Path::new(output);
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let container = match extension {
+        Some("mp4") | Some("mov") => Some(Container::Mp4),
+        Some("mkv") => Some(Container::Matroska),
+        Some("ts") => Some(Container::MpegTs),
+        _ => None,
+    };
+    if container.is_some() || matches!(extension, Some("m3u8") | Some("mpd")) {
+        if let Some(parent) = path.parent().and_then(|p| p.to_str()) {
+            parse_dir(if parent.is_empty() { "." } else { parent })?;
+        }
+        let seg = SegmentOptions::new(path.to_path_buf());
+        return Ok(match (container, extension) {
+            (Some(container), _) => Output::File {
+                path: path.to_path_buf(),
+                container,
+                split: None,
+            },
+            (None, Some("m3u8")) => Output::Hls(seg),
+            _ => Output::Dash(seg),
+        });
+    }
+    let raw = match extension {
+        Some("yuv") => Some(RawFormat::I420),
+        Some("rgb") => Some(RawFormat::Rgb),
+        _ => None,
+    };
+    if let Some(format) = raw {
+        if let Some(parent) = path.parent().and_then(|p| p.to_str()) {
+            parse_dir(if parent.is_empty() { "." } else { parent })?;
+        }
+        return Ok(Output::Raw {
+            path: path.to_path_buf(),
+            format,
+        });
+    }
+    let animation = match extension {
+        Some("gif") => Some(AnimationFormat::Gif),
+        Some("webp") => Some(AnimationFormat::WebP),
+        _ => None,
+    };
+    if let Some(format) = animation {
+        if let Some(parent) = path.parent().and_then(|p| p.to_str()) {
+            parse_dir(if parent.is_empty() { "." } else { parent })?;
+        }
+        return Ok(Output::Animation(AnimationOptions::new(
+            path.to_path_buf(),
+            format,
+        )));
+    }
+    if let Some(dest) = output.strip_prefix("rtp://") {
+        if let Some((host, port)) = dest.rsplit_once(':') {
+            if let Ok(port) = port.parse::<u16>() {
+                if !host.is_empty() {
+                    return Ok(Output::Rtp {
+                        host: host.to_string(),
+                        port,
+                        sdp: None,
+                    });
+                }
+            }
+        }
+    }
+    Err(super::Error::Format(format!(
+        "{} (supported outputs: screen, -, rtp://host:port, *.mp4, *.mov, *.mkv, *.ts, *.m3u8, *.mpd, *.gif, *.webp, *.yuv, *.rgb)",
+        output
+    )))
+}
+
+/// Validates an RTSP mount point: a non-empty path without whitespace or query.
+/// The leading '/' is added if missing
+pub fn parse_mount(mount: &str) -> Result<String, Error> {
+    let path = mount.trim_start_matches('/');
+    if path.is_empty() || path.contains(|c: char| c.is_whitespace() || c == '?' || c == '#') {
+        return Err(super::Error::Format(format!(
+            "{} (expected a path such as /stream)",
+            mount
+        )));
+    }
+    Ok(format!("/{}", path))
+}
+
+/// Validates a segment container (ts, fmp4|cmaf).
+/// Case insensitive
+pub fn parse_segment_format(format: &str) -> Result<SegmentFormat, Error> {
+    match format.to_ascii_lowercase().as_str() {
+        "ts" => Ok(SegmentFormat::Ts),
+        "fmp4" | "cmaf" => Ok(SegmentFormat::Fmp4),
+        _ => Err(super::Error::Format(format!(
+            "{} (supported segment formats: ts, fmp4)",
+            format
+        ))),
+    }
+}
+
+/// Validates a resolution given as WIDTHxHEIGHT, both positive
+pub fn parse_resolution(resolution: &str) -> Result<(i32, i32), Error> {
+    let parsed = resolution
+        .split_once(['x', 'X'])
+        .and_then(|(w, h)| Some((w.parse::<i32>().ok()?, h.parse::<i32>().ok()?)))
+        .filter(|(w, h)| *w > 0 && *h > 0);
+    parsed.ok_or_else(|| {
+        super::Error::Format(format!(
+            "{} (expected WIDTHxHEIGHT, e.g. 640x360)",
+            resolution
+        ))
+    })
+}
+
+/// Validates an output: a target (see [`parse_target`]) optionally followed by comma-separated
+/// `width=`, `height=` and `format=` overrides, e.g. `rec.mp4,width=1280,height=720`.
+/// `width` and `height` go together
+pub fn parse_output(output: &str) -> Result<OutputOptions, Error> {
+    let mut parts = output.split(',');
+    let target = parse_target(parts.next().unwrap_or_default())?;
+
+    let (mut width, mut height, mut format) = (None, None, None);
+    for part in parts {
+        let invalid = || {
+            super::Error::Format(format!(
+                "{} (expected width=W, height=H or format=F after the output)",
+                part
+            ))
+        };
+        let (key, value) = part.split_once('=').ok_or_else(invalid)?;
+        match key {
+            "width" => width = Some(value.parse::<i32>().map_err(|_| invalid())?),
+            "height" => height = Some(value.parse::<i32>().map_err(|_| invalid())?),
+            "format" => format = Some(parse_format(value)?),
+            _ => return Err(invalid()),
+        }
+    }
+
+    let width_height = match (width, height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+        (None, None) => None,
+        _ => {
+            return Err(super::Error::Format(format!(
+                "{} (width and height must both be given, and positive)",
+                output
+            )))
+        }
+    };
+    Ok(OutputOptions {
+        target,
+        width_height,
+        format,
+    })
+}
+
+/// Validates a frame file name pattern: a .png or .jpg file name with a %d-style (frame number)
+/// or %t (timestamp) placeholder, in a writable directory, or one that can be created
+pub fn parse_frame_pattern(pattern: &str) -> Result<PathBuf, Error> {
+    let path = parse_image(pattern)?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if super::expand_frame_pattern(&name, 0, 0) == super::expand_frame_pattern(&name, 1, 1) {
+        return Err(super::Error::Format(format!(
+            "{} (the file name needs a %d or %t placeholder)",
+            pattern
+        )));
+    }
+    Ok(path)
+}
+
+/// Validates an image file name: a supported extension (png, jpg), in a writable directory,
+/// or one that can be created
+pub fn parse_image(image: &str) -> Result<PathBuf, Error> {
+    let path = PathBuf::from(image);
+    if ImageFormat::from_path(&path).is_none() {
+        return Err(super::Error::Format(format!(
+            "{} (supported image formats: png, jpg)",
+            image
+        )));
+    }
+    if let Some(parent) = path.parent().and_then(|p| p.to_str()) {
+        parse_dir(if parent.is_empty() { "." } else { parent })?;
+    }
+    Ok(path)
+}
+
+/// Validates a transition between files played in a row: `cut`, `crossfade`, `dip-to-black`
+/// or `wipe`, optionally followed by `:SECONDS` (1 by default). Case insensitive
+pub fn parse_transition(transition: &str) -> Result<super::Transition, Error> {
+    let (kind, secs) = match transition.split_once(':') {
+        Some((kind, secs)) => (kind, Some(parse_seconds(secs)?)),
+        None => (transition, None),
+    };
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "cut" => super::TransitionKind::Cut,
+        "crossfade" | "fade" => super::TransitionKind::Crossfade,
+        "dip-to-black" | "dip" => super::TransitionKind::DipToBlack,
+        "wipe" => super::TransitionKind::Wipe,
+        _ => {
+            return Err(super::Error::Format(format!(
+                "{} (supported transitions: cut, crossfade, dip-to-black, wipe)",
+                transition
+            )))
+        }
+    };
+    let duration_secs = match kind {
+        super::TransitionKind::Cut => 0.0,
+        _ => secs.unwrap_or(1.0),
+    };
+    if kind != super::TransitionKind::Cut && duration_secs <= 0.0 {
+        return Err(super::Error::Format(format!(
+            "{} (the transition needs a duration)",
+            transition
+        )));
+    }
+    Ok(super::Transition {
+        kind,
+        duration_secs,
+    })
+}
+
+/// Validates a corner of the picture: top-left, top-right, bottom-left or bottom-right.
+/// Case insensitive
+pub fn parse_corner(corner: &str) -> Result<super::Corner, Error> {
+    match corner.to_ascii_lowercase().as_str() {
+        "top-left" => Ok(super::Corner::TopLeft),
+        "top-right" => Ok(super::Corner::TopRight),
+        "bottom-left" => Ok(super::Corner::BottomLeft),
+        "bottom-right" => Ok(super::Corner::BottomRight),
+        _ => Err(super::Error::Format(format!(
+            "{} (supported corners: top-left, top-right, bottom-left, bottom-right)",
+            corner
+        ))),
+    }
+}
+
+/// Validates an opacity: a number from 0 (invisible) to 1 (opaque)
+pub fn parse_opacity(opacity: &str) -> Result<f64, Error> {
+    match opacity.parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(super::Error::Format(format!(
+            "{} (expected a number from 0 to 1)",
+            opacity
+        ))),
+    }
+}
+
+/// Validates a duration or timestamp in seconds: a non-negative number
+pub fn parse_seconds(secs: &str) -> Result<f64, Error> {
+    match secs.parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(super::Error::Format(format!(
+            "{} (expected a number of seconds)",
+            secs
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransitionKind;
+
+    /// An empty directory of its own for a test, removed with everything in it when dropped
+    struct ScratchDir(PathBuf);
+
+    impl ScratchDir {
+        /// Create the directory of `test`, with `files` in it
+        fn new(test: &str, files: &[&str]) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("hc-validator-{}-{}", std::process::id(), test));
+            let _ = fs::remove_dir_all(&dir);
+            for file in files {
+                let path = dir.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+            fs::create_dir_all(&dir).unwrap();
+            ScratchDir(dir)
+        }
+    }
+
+    impl std::ops::Deref for ScratchDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn transitions_last_a_second_by_default() {
+        let transition = parse_transition("crossfade").unwrap();
+        assert_eq!(transition.kind, TransitionKind::Crossfade);
+        assert_eq!(transition.duration_secs, 1.0);
+
+        let transition = parse_transition("Wipe:2.5").unwrap();
+        assert_eq!(transition.kind, TransitionKind::Wipe);
+        assert_eq!(transition.duration_secs, 2.5);
+
+        assert_eq!(
+            parse_transition("dip").unwrap().kind,
+            TransitionKind::DipToBlack
+        );
+    }
+
+    #[test]
+    fn cuts_take_no_time() {
+        let transition = parse_transition("cut:3").unwrap();
+        assert_eq!(transition.kind, TransitionKind::Cut);
+        assert_eq!(transition.duration_secs, 0.0);
+    }
+
+    #[test]
+    fn invalid_transitions() {
+        assert!(parse_transition("spin").is_err());
+        assert!(parse_transition("crossfade:0").is_err());
+        assert!(parse_transition("crossfade:-1").is_err());
+        assert!(parse_transition("crossfade:long").is_err());
+    }
+
+    #[test]
+    fn stdin_input() {
+        assert!(matches!(parse_fname("-"), Ok(Input::Stdin)));
+    }
+
+    #[test]
+    fn missing_input_file() {
+        let dir = ScratchDir::new("missing", &[]);
+        assert!(matches!(
+            parse_fname(&dir.join("clip.mp4").to_string_lossy()),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn playlists_list_files_relative_to_them() {
+        let dir = ScratchDir::new("playlist", &["a.mp4", "sub/b.mp4", "c.mp4"]);
+        let absolute = dir.join("c.mp4");
+        let playlist = dir.join("list.m3u8");
+        fs::write(
+            &playlist,
+            format!(
+                "#EXTM3U\n#EXTINF:10,First\na.mp4\n\n  sub/b.mp4  \n{}\n",
+                absolute.display()
+            ),
+        )
+        .unwrap();
+        match parse_fname(&playlist.to_string_lossy()) {
+            Ok(Input::Playlist(files)) => assert_eq!(
+                files,
+                vec![dir.join("a.mp4"), dir.join("sub/b.mp4"), absolute]
+            ),
+            other => panic!("not a playlist: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn playlist_extensions() {
+        let dir = ScratchDir::new("playlist-extensions", &["a.mp4"]);
+        for name in ["list.m3u", "list.M3U8", "list.txt"] {
+            let playlist = dir.join(name);
+            fs::write(&playlist, "a.mp4\n").unwrap();
+            assert!(
+                matches!(
+                    parse_fname(&playlist.to_string_lossy()),
+                    Ok(Input::Playlist(_))
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_playlists() {
+        let dir = ScratchDir::new("invalid-playlists", &["a.mp4"]);
+        let empty = dir.join("empty.m3u");
+        fs::write(&empty, "#EXTM3U\n\n").unwrap();
+        assert!(matches!(
+            parse_fname(&empty.to_string_lossy()),
+            Err(Error::Format(_))
+        ));
+
+        let missing = dir.join("missing.m3u");
+        fs::write(&missing, "a.mp4\nb.mp4\n").unwrap();
+        assert!(matches!(
+            parse_fname(&missing.to_string_lossy()),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound && e.to_string().contains("b.mp4")
+        ));
+
+        fs::write(dir.join("inner.m3u"), "a.mp4\n").unwrap();
+        let nested = dir.join("nested.m3u");
+        fs::write(&nested, "inner.m3u\n").unwrap();
+        assert!(matches!(
+            parse_fname(&nested.to_string_lossy()),
+            Err(Error::Format(_))
+        ));
+    }
+
+    #[test]
+    fn image_sequences_start_at_0_or_1() {
+        let dir = ScratchDir::new("images", &["from0/f_0000.png", "from1/f_0001.jpg"]);
+        assert!(matches!(
+            parse_fname(&dir.join("from0/f_%04d.png").to_string_lossy()),
+            Ok(Input::ImageSequence { start_index: 0, .. })
+        ));
+        assert!(matches!(
+            parse_fname(&dir.join("from1/f_%04d.jpg").to_string_lossy()),
+            Ok(Input::ImageSequence { pattern, start_index: 1 }) if pattern == dir.join("from1/f_%04d.jpg")
+        ));
+    }
+
+    #[test]
+    fn invalid_image_sequences() {
+        let dir = ScratchDir::new("invalid-images", &["f_0002.png", "f_0000.bmp"]);
+        assert!(matches!(
+            parse_fname(&dir.join("f_%04d.png").to_string_lossy()),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(matches!(
+            parse_fname(&dir.join("f_%04d.bmp").to_string_lossy()),
+            Err(Error::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_patterns_are_case_insensitive() {
+        assert_eq!(parse_test_pattern("Ball").unwrap(), "ball");
+        assert!(parse_test_pattern("smpte-rp-219").is_ok());
+        assert!(parse_test_pattern("plaid").is_err());
+    }
+
+    #[test]
+    fn test_pattern_inputs() {
+        assert!(matches!(
+            parse_fname("test://snow"),
+            Ok(Input::TestPattern(pattern)) if pattern == "snow"
+        ));
+        assert!(matches!(parse_fname("test://plaid"), Err(Error::Format(_))));
+    }
+
+    #[test]
+    fn network_uris() {
+        assert!(matches!(
+            parse_fname("rtsp://camera.local/stream"),
+            Ok(Input::Uri(uri)) if uri == "rtsp://camera.local/stream"
+        ));
+        assert!(matches!(
+            parse_fname("UDP://0.0.0.0:5000"),
+            Ok(Input::Uri(_))
+        ));
+        assert!(matches!(parse_fname("srt://host:9000"), Ok(Input::Uri(_))));
+    }
+
+    #[test]
+    fn network_uris_need_a_known_scheme_and_a_local_port() {
+        assert!(matches!(
+            parse_fname("ftp://host/file.mp4"),
+            Err(Error::Format(_))
+        ));
+        assert!(matches!(parse_fname("http://"), Err(Error::Format(_))));
+        assert!(matches!(
+            parse_fname("udp://0.0.0.0"),
+            Err(Error::Format(_))
+        ));
+        assert!(matches!(parse_fname("rtp://:port"), Err(Error::Format(_))));
+    }
+
+    #[test]
+    fn output_targets() {
+        let target = |output: &str| parse_output(output).unwrap().target;
+        assert!(matches!(target("screen"), Output::Screen));
+        assert!(matches!(
+            target("-"),
+            Output::Stdout {
+                container: Container::MpegTs
+            }
+        ));
+        assert!(matches!(
+            target("rec.mkv"),
+            Output::File {
+                container: Container::Matroska,
+                split: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            target("rec.mov"),
+            Output::File {
+                container: Container::Mp4,
+                ..
+            }
+        ));
+        assert!(matches!(target("out/stream.m3u8"), Output::Hls(_)));
+        assert!(matches!(target("out/stream.mpd"), Output::Dash(_)));
+        assert!(matches!(
+            target("frames.yuv"),
+            Output::Raw {
+                format: RawFormat::I420,
+                ..
+            }
+        ));
+        assert!(matches!(target("preview.gif"), Output::Animation(_)));
+    }
+
+    #[test]
+    fn rtp_outputs() {
+        assert!(matches!(
+            parse_output("rtp://127.0.0.1:5004").unwrap().target,
+            Output::Rtp { host, port: 5004, sdp: None } if host == "127.0.0.1"
+        ));
+        assert!(parse_output("rtp://127.0.0.1").is_err());
+        assert!(parse_output("rtp://:5004").is_err());
+        assert!(parse_output("rtp://127.0.0.1:70000").is_err());
+    }
+
+    #[test]
+    fn unsupported_outputs() {
+        assert!(matches!(parse_output("rec.avi"), Err(Error::Format(_))));
+        assert!(matches!(parse_output("window"), Err(Error::Format(_))));
+    }
+
+    #[test]
+    fn output_directories_are_checked_not_created() {
+        let dir = ScratchDir::new("output-dirs", &["file"]);
+        let nested = dir.join("a/b");
+        assert_eq!(parse_dir(&nested.to_string_lossy()).unwrap(), nested);
+        assert!(parse_output(&nested.join("rec.mp4").to_string_lossy()).is_ok());
+        assert!(!dir.join("a").exists());
+
+        assert!(matches!(
+            parse_dir(&dir.join("file/sub").to_string_lossy()),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotADirectory
+        ));
+    }
+
+    #[test]
+    fn output_overrides() {
+        let output = parse_output("rec.mp4,width=1280,height=720,format=H264").unwrap();
+        assert_eq!(output.width_height, Some((1280, 720)));
+        assert!(matches!(output.format, Some(crate::VideoFormat::H264)));
+
+        let output = parse_output("rec.mp4").unwrap();
+        assert_eq!(output.width_height, None);
+        assert!(output.format.is_none());
+    }
+
+    #[test]
+    fn invalid_output_overrides() {
+        assert!(parse_output("rec.mp4,width=1280").is_err());
+        assert!(parse_output("rec.mp4,width=0,height=720").is_err());
+        assert!(parse_output("rec.mp4,width=wide,height=720").is_err());
+        assert!(parse_output("rec.mp4,bitrate=1000").is_err());
+        assert!(parse_output("rec.mp4,format=vp8").is_err());
+        assert!(parse_output("rec.mp4,1280").is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use gstreamer::prelude::ObjectExt;
+use gstreamer::Element;
+use log::debug;
+
+use util::{Corner, MosaicOptions, PipOptions};
+
+use super::registry::ElementRegistry;
+use super::{Error, GstreamerDecoder, SourceBranch};
+use crate::{Error as VideoError, VideoInput};
+
+/// Columns, rows and tile size (width x height) of a mosaic of `count` tiles on a `canvas`:
+/// the requested number of `columns`, or as many as needed for a square grid, and as many rows
+/// as needed for all the tiles, which share the picture evenly
+fn grid(
+    count: u32,
+    columns: Option<u32>,
+    canvas: (i32, i32),
+) -> Result<(u32, u32, (i32, i32)), VideoError> {
+    let columns = columns
+        .unwrap_or_else(|| (1..=count).find(|c| c * c >= count).unwrap_or(1))
+        .min(count.max(1));
+    let rows = count.div_ceil(columns);
+    let (width, height) = canvas;
+    // Even sizes, which all the raw formats accept
+    let tile_width = (width / columns as i32) & !1;
+    let tile_height = (height / rows as i32) & !1;
+    if tile_width < 16 || tile_height < 16 {
+        return Err(VideoError::Gstreamer(Error::Composite(format!(
+            "{} tiles of {}x{} are too small, make the picture larger",
+            count, tile_width, tile_height
+        ))));
+    }
+    Ok((columns, rows, (tile_width, tile_height)))
+}
+
+/// Where and how an input is drawn on the composed picture
+struct Placement {
+    /// Horizontal position of the top left corner of the input (border excluded), in pixels
+    x: i32,
+    /// Vertical position of the top left corner of the input (border excluded), in pixels
+    y: i32,
+    /// Size the input is scaled to (width x height)
+    width_height: (i32, i32),
+    /// Opacity, from 0 (invisible) to 1 (opaque)
+    alpha: f64,
+    /// Width of the white border around the input, in pixels
+    border: i32,
+    /// Text written in the top left corner of the input, if any
+    label: Option<String>,
+}
+
+impl GstreamerDecoder {
+    /// Create the source steps of a picture-in-picture: the main input fills the picture and
+    /// the webcam is drawn over it, in a corner, by [`compositesource`](Self::compositesource).
+    ///
+    /// The picture has the requested size, or the main input's when it is known up front
+    /// (files are probed).
+    pub(super) fn pipsource(
+        names: &mut ElementRegistry,
+        main: VideoInput,
+        opts: PipOptions,
+        source_branches: &mut Vec<SourceBranch>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let canvas = match opts.canvas {
+            Some(canvas) => canvas,
+            None => match &main {
+                VideoInput::File(fname) => Some(Self::probe_clip(fname)?.width_height),
+                VideoInput::Raw(_, raw) => Some(raw.width_height),
+                VideoInput::TestPattern(test) => test.width_height,
+                VideoInput::Webcam(webcam) => webcam.width_height,
+                _ => None,
+            }
+            .ok_or_else(|| {
+                VideoError::Gstreamer(Error::Composite(format!(
+                    "the size of {} isn't known up front, set --width and --height",
+                    main
+                )))
+            })?,
+        };
+        let (width, height) = canvas;
+        let (inset_width, inset_height) = opts.size;
+        let outer_width = inset_width + 2 * opts.border;
+        let outer_height = inset_height + 2 * opts.border;
+        if outer_width + 2 * opts.margin > width || outer_height + 2 * opts.margin > height {
+            return Err(VideoError::Gstreamer(Error::Composite(format!(
+                "a {}x{} inset with a {} px border and a {} px margin doesn't fit in {}x{}",
+                inset_width, inset_height, opts.border, opts.margin, width, height
+            ))));
+        }
+        let left = opts.margin + opts.border;
+        let right = width - opts.margin - opts.border - inset_width;
+        let top = opts.margin + opts.border;
+        let bottom = height - opts.margin - opts.border - inset_height;
+        let (x, y) = match opts.position {
+            Corner::TopLeft => (left, top),
+            Corner::TopRight => (right, top),
+            Corner::BottomLeft => (left, bottom),
+            Corner::BottomRight => (right, bottom),
+        };
+
+        let layers = vec![
+            (
+                main,
+                Placement {
+                    x: 0,
+                    y: 0,
+                    width_height: canvas,
+                    alpha: 1.0,
+                    border: 0,
+                    label: None,
+                },
+            ),
+            (
+                VideoInput::Webcam(opts.webcam),
+                Placement {
+                    x,
+                    y,
+                    width_height: opts.size,
+                    alpha: opts.opacity,
+                    border: opts.border,
+                    label: None,
+                },
+            ),
+        ];
+        Self::compositesource(names, layers, source_branches)
+    }
+
+    /// Create the source steps of a mosaic: the inputs are tiled into a grid, row by row, by
+    /// [`compositesource`](Self::compositesource), each with its label.
+    ///
+    /// The grid has the requested number of columns, or as many as needed for a square grid,
+    /// and as many rows as needed for all the inputs; the tiles share the picture evenly.
+    pub(super) fn mosaicsource(
+        names: &mut ElementRegistry,
+        tiles: Vec<VideoInput>,
+        opts: MosaicOptions,
+        source_branches: &mut Vec<SourceBranch>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let count = tiles.len() as u32;
+        let (columns, rows, (tile_width, tile_height)) = grid(count, opts.columns, opts.canvas)?;
+        debug!(
+            "{} tiles in {} columns and {} rows, of {}x{} each",
+            count, columns, rows, tile_width, tile_height
+        );
+
+        let layers = tiles
+            .into_iter()
+            .enumerate()
+            .map(|(i, tile)| {
+                let (row, column) = (i as i32 / columns as i32, i as i32 % columns as i32);
+                let label = opts.labels.get(i).filter(|label| !label.is_empty());
+                (
+                    tile,
+                    Placement {
+                        x: column * tile_width,
+                        y: row * tile_height,
+                        width_height: (tile_width, tile_height),
+                        alpha: 1.0,
+                        border: 0,
+                        label: label.cloned(),
+                    },
+                )
+            })
+            .collect();
+        Self::compositesource(names, layers, source_branches)
+    }
+
+    /// Create the source steps drawing several inputs on one picture, in order (the last one on
+    /// top). Each input gets its own chain in `source_branches`:
+    /// 1. the source steps of the input
+    /// 1. [`videoscale`](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoscale.html?gi-language=c#videoscale-page)
+    ///    and a [`capsfilter`](https://gstreamer.freedesktop.org/documentation/coreelements/capsfilter.html?gi-language=c#capsfilter-page)
+    ///    with the size of the input on the picture; square pixels make the scaler keep the
+    ///    aspect ratio of the input and fill the rest with black bars
+    /// 1. if the input has a label, a [`textoverlay`](https://gstreamer.freedesktop.org/documentation/pango/textoverlay.html?gi-language=c)
+    ///    that writes it
+    /// 1. if the input has a border, a [`videobox`](https://gstreamer.freedesktop.org/documentation/videobox/index.html?gi-language=c)
+    ///    that adds it
+    ///
+    /// and goes to a sink pad of the
+    /// [`compositor`](https://gstreamer.freedesktop.org/documentation/compositor/index.html?gi-language=c),
+    /// which holds its position and opacity. The source steps are the compositor and a
+    /// [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page).
+    fn compositesource(
+        names: &mut ElementRegistry,
+        layers: Vec<(VideoInput, Placement)>,
+        source_branches: &mut Vec<SourceBranch>,
+    ) -> Result<Vec<Element>, VideoError> {
+        // {source steps} ! videoscale ! caps [! textoverlay] [! videobox] ! compositor-0.sink_0  [...]
+        // compositor name=compositor-0 sink_0::xpos=... ! videoconvert !
+        let compositor = names
+            .make_with_role("compositor", "compositor")
+            .property_from_str("background", "black")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        for (input, placement) in layers {
+            let mut steps = Self::source(names, input, source_branches)?;
+            let (width, height) = placement.width_height;
+            steps.extend([
+                names
+                    .make("videoscale")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                names
+                    .make_with_role("capsfilter", "layer")
+                    .property(
+                        "caps",
+                        gstreamer::Caps::builder("video/x-raw")
+                            .field("width", width)
+                            .field("height", height)
+                            .field("pixel-aspect-ratio", gstreamer::Fraction::new(1, 1))
+                            .build(),
+                    )
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ]);
+            if let Some(label) = placement.label {
+                steps.push(
+                    names
+                        .make_with_role("textoverlay", "label")
+                        .property("text", label)
+                        .property_from_str("valignment", "top")
+                        .property_from_str("halignment", "left")
+                        .property("shaded-background", true)
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+            }
+            if placement.border > 0 {
+                // Negative borders add pixels around the frame instead of cropping it
+                let border = -placement.border;
+                steps.push(
+                    names
+                        .make_with_role("videobox", "border")
+                        .property("left", border)
+                        .property("right", border)
+                        .property("top", border)
+                        .property("bottom", border)
+                        .property_from_str("fill", "white")
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+            }
+
+            let pad = Self::request_sink_pad(&compositor)?;
+            pad.set_property("xpos", placement.x - placement.border);
+            pad.set_property("ypos", placement.y - placement.border);
+            pad.set_property("alpha", placement.alpha);
+            source_branches.push(SourceBranch { steps, pad });
+        }
+        Ok(vec![
+            compositor,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grids_are_as_square_as_possible() {
+        assert_eq!(grid(1, None, (1280, 720)).unwrap(), (1, 1, (1280, 720)));
+        assert_eq!(grid(2, None, (1280, 720)).unwrap(), (2, 1, (640, 720)));
+        assert_eq!(grid(4, None, (1280, 720)).unwrap(), (2, 2, (640, 360)));
+        assert_eq!(grid(5, None, (1280, 720)).unwrap(), (3, 2, (426, 360)));
+        assert_eq!(grid(9, None, (1280, 720)).unwrap(), (3, 3, (426, 240)));
+        assert_eq!(grid(10, None, (1280, 720)).unwrap(), (4, 3, (320, 240)));
+    }
+
+    #[test]
+    fn grids_have_the_requested_columns() {
+        assert_eq!(grid(4, Some(4), (1280, 720)).unwrap(), (4, 1, (320, 720)));
+        assert_eq!(grid(5, Some(2), (1280, 720)).unwrap(), (2, 3, (640, 240)));
+        // No more columns than tiles
+        assert_eq!(grid(2, Some(5), (1280, 720)).unwrap(), (2, 1, (640, 720)));
+    }
+
+    #[test]
+    fn tile_sizes_are_even() {
+        assert_eq!(grid(3, Some(3), (100, 51)).unwrap(), (3, 1, (32, 50)));
+    }
+
+    #[test]
+    fn tiles_can_be_too_small() {
+        assert!(grid(16, None, (60, 60)).is_err());
+        assert!(grid(100, Some(100), (1280, 720)).is_err());
+    }
+}
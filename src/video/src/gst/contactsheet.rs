@@ -0,0 +1,273 @@
+use std::fs;
+use std::path::Path;
+
+use gstreamer::prelude::{
+    Cast, ElementExt, ElementExtManual, GstBinExt, GstBinExtManual, ObjectExt,
+};
+use gstreamer::{
+    Buffer, Caps, ClockTime, Element, MessageType, MessageView, Pipeline, Sample, SeekFlags,
+};
+use gstreamer_app::AppSink;
+use gstreamer_video::VideoInfo;
+use log::{debug, info};
+
+use util::ContactSheetOptions;
+
+use super::frames::{image_caps, CONVERT_TIMEOUT};
+use super::registry::ElementRegistry;
+use super::{Error, GstreamerDecoder, Preparation};
+use crate::Error as VideoError;
+
+/// Height of the strip under each tile where its timestamp is printed
+const LABEL_HEIGHT: u32 = 24;
+
+/// Space around the tiles, in pixels
+const GAP: usize = 4;
+
+/// Gray level of the space around the tiles
+const BACKGROUND: u8 = 0x20;
+
+/// Bytes per pixel of the tiles and of the sheet (RGBx)
+const PIXEL_SIZE: usize = 4;
+
+/// Steps of the pipeline the frames are taken from: the source steps of the file, and each
+/// frame scaled down to a tile, with a black strip underneath for the timestamp if requested
+///
+/// ```text
+/// {file source steps} ! videoscale ! capsfilter [! videobox ! textoverlay] ! videoconvert ! appsink
+/// ```
+fn steps(
+    names: &mut ElementRegistry,
+    fname: &str,
+    tile: Caps,
+    opts: &ContactSheetOptions,
+) -> Result<Vec<Element>, VideoError> {
+    let mut steps = GstreamerDecoder::filesource(names, fname.to_string())?;
+    steps.extend([
+        names
+            .make_with_role("videoscale", "scaler")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        names
+            .make_with_role("capsfilter", "tile")
+            .property("caps", tile)
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+    ]);
+    if opts.timestamps {
+        steps.extend([
+            names
+                .make("videobox")
+                .property("bottom", -(LABEL_HEIGHT as i32))
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("textoverlay", "label")
+                .property_from_str("valignment", "bottom")
+                .property_from_str("halignment", "center")
+                .property("ypad", 4i32)
+                .property("font-desc", "Sans 11")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ]);
+    }
+    steps.extend([
+        names
+            .make("videoconvert")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        AppSink::builder()
+            .name(names.name_for_role("appsink", "sink"))
+            .caps(&Caps::builder("video/x-raw").field("format", "RGBx").build())
+            .sync(false)
+            .build()
+            .upcast(),
+    ]);
+    Ok(steps)
+}
+
+/// Caps of the tiles: the requested size, or the requested width and the height that keeps
+/// the aspect ratio of the video, `width_height`
+fn tile_caps(opts: &ContactSheetOptions, (width, height): (i32, i32)) -> Caps {
+    let tile_width = opts.tile_width as i32;
+    let tile_height = match opts.tile_height {
+        Some(tile_height) => tile_height as i32,
+        // Even heights, which all the raw formats accept
+        None => ((tile_width * height / width.max(1)) & !1).max(2),
+    };
+    Caps::builder("video/x-raw")
+        .field("width", tile_width)
+        .field("height", tile_height)
+        .field("pixel-aspect-ratio", gstreamer::Fraction::new(1, 1))
+        .build()
+}
+
+/// Format a timestamp as `h:mm:ss`
+fn timestamp(t: ClockTime) -> String {
+    let secs = t.seconds();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Wait until the pipeline has prerolled, after a state change or a seek
+fn preroll(pipeline: &Pipeline) -> Result<(), VideoError> {
+    let bus = pipeline.bus().ok_or(VideoError::Gstreamer(Error::Bus))?;
+    let msg = bus.timed_pop_filtered(
+        ClockTime::NONE,
+        &[MessageType::AsyncDone, MessageType::Error, MessageType::Eos],
+    );
+    match msg.as_ref().map(|msg| msg.view()) {
+        Some(MessageView::AsyncDone(_)) => Ok(()),
+        Some(MessageView::Error(err)) => Err(VideoError::Gstreamer(Error::ContactSheet(
+            err.error().to_string(),
+        ))),
+        _ => Err(VideoError::Gstreamer(Error::ContactSheet(
+            "no more frames".to_string(),
+        ))),
+    }
+}
+
+/// Take `columns * rows` frames evenly spaced over the `duration` of the file, one per tile:
+/// the frame at the middle of each equal part of the video
+fn grab_tiles(
+    pipeline: &Pipeline,
+    sink: &AppSink,
+    label: Option<&Element>,
+    duration: ClockTime,
+    opts: &ContactSheetOptions,
+) -> Result<Vec<Sample>, VideoError> {
+    pipeline
+        .set_state(gstreamer::State::Paused)
+        .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+    preroll(pipeline)?;
+
+    let count = u64::from(opts.columns * opts.rows);
+    debug!("Taking {} frames from {} of video", count, duration);
+
+    let mut tiles = vec![];
+    for n in 0..count {
+        let at = ClockTime::from_nseconds(duration.nseconds() / (2 * count) * (2 * n + 1));
+        if let Some(label) = label {
+            label.set_property("text", timestamp(at));
+        }
+        pipeline
+            .seek_simple(SeekFlags::FLUSH | SeekFlags::ACCURATE, at)
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        preroll(pipeline)?;
+        tiles.push(sink.pull_preroll().map_err(|e| {
+            VideoError::Gstreamer(Error::ContactSheet(format!("no frame at {}: {}", at, e)))
+        })?);
+    }
+    Ok(tiles)
+}
+
+/// Lay the tiles out in a grid, row by row, in one RGBx frame
+fn compose(tiles: &[Sample], columns: u32) -> Result<Sample, VideoError> {
+    let info = |tile: &Sample| {
+        tile.caps()
+            .and_then(|caps| VideoInfo::from_caps(caps).ok())
+            .ok_or_else(|| {
+                VideoError::Gstreamer(Error::ContactSheet("frame without video caps".to_string()))
+            })
+    };
+    let first = info(&tiles[0])?;
+    let (tile_width, tile_height) = (first.width() as usize, first.height() as usize);
+    let columns = columns as usize;
+    let rows = tiles.len().div_ceil(columns);
+    let width = columns * (tile_width + GAP) + GAP;
+    let height = rows * (tile_height + GAP) + GAP;
+
+    let mut data = vec![BACKGROUND; width * height * PIXEL_SIZE];
+    for (n, tile) in tiles.iter().enumerate() {
+        let info = info(tile)?;
+        let stride = info.stride()[0] as usize;
+        let Some(buffer) = tile.buffer().and_then(|buf| buf.map_readable().ok()) else {
+            continue;
+        };
+        let (x, y) = (
+            GAP + n % columns * (tile_width + GAP),
+            GAP + n / columns * (tile_height + GAP),
+        );
+        let line = tile_width.min(info.width() as usize) * PIXEL_SIZE;
+        for row in 0..tile_height.min(info.height() as usize) {
+            let src = &buffer.as_slice()[row * stride..row * stride + line];
+            let dst = ((y + row) * width + x) * PIXEL_SIZE;
+            data[dst..dst + line].copy_from_slice(src);
+        }
+    }
+
+    let caps = VideoInfo::builder(
+        gstreamer_video::VideoFormat::Rgbx,
+        width as u32,
+        height as u32,
+    )
+    .build()
+    .and_then(|info| info.to_caps())
+    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+    Ok(Sample::builder()
+        .buffer(&Buffer::from_mut_slice(data))
+        .caps(&caps)
+        .build())
+}
+
+/// Write a contact sheet of the video file at `path`: a grid of evenly spaced frames, scaled
+/// down to tiles, in one PNG or JPEG image.
+///
+/// The size and duration of the video are probed first, like for the other file inputs, and
+/// the file is read by the same source steps as the pipeline's. The frames are taken by
+/// seeking the paused pipeline to each of them, then laid out and converted to an image with
+/// `gst_video_convert_sample()`.
+pub fn contact_sheet(path: &Path, opts: &ContactSheetOptions) -> Result<(), VideoError> {
+    gstreamer::init().map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+
+    let fname = path.to_string_lossy().to_string();
+    let clip = GstreamerDecoder::probe_clip(&fname)?;
+    let duration = clip
+        .duration
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| {
+            VideoError::Gstreamer(Error::ContactSheet(
+                "can't tell the duration of the input".to_string(),
+            ))
+        })?;
+
+    let mut names = ElementRegistry::default();
+    let steps = steps(&mut names, &fname, tile_caps(opts, clip.width_height), opts)?;
+    let pipeline = Pipeline::with_name("hc-contact-sheet");
+    pipeline
+        .add_many(steps.iter())
+        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+    GstreamerDecoder::link_chain(&steps)?;
+    let Some(sink) = steps
+        .last()
+        .and_then(|sink| sink.clone().downcast::<AppSink>().ok())
+    else {
+        return Err(VideoError::Gstreamer(Error::ContactSheet(
+            "missing appsink".to_string(),
+        )));
+    };
+    let label = names
+        .names("label")
+        .first()
+        .and_then(|name| pipeline.by_name(name));
+
+    let tiles = grab_tiles(&pipeline, &sink, label.as_ref(), duration, opts);
+    let _ = pipeline.set_state(gstreamer::State::Null);
+    let sheet = compose(&tiles?, opts.columns)?;
+
+    let image = gstreamer_video::convert_sample(&sheet, &image_caps(opts.format), CONVERT_TIMEOUT)
+        .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+    let buffer = image
+        .buffer()
+        .and_then(|buf| buf.map_readable().ok())
+        .ok_or_else(|| VideoError::Gstreamer(Error::ContactSheet("empty image".to_string())))?;
+    if let Some(dir) = opts
+        .image
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        Preparation::Dir(dir.to_path_buf()).apply()?;
+    }
+    fs::write(&opts.image, buffer.as_slice()).map_err(VideoError::Io)?;
+    info!("Contact sheet written to {}", opts.image.display());
+    Ok(())
+}
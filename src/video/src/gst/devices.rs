@@ -0,0 +1,127 @@
+use gstreamer::prelude::{DeviceExt, DeviceMonitorExtManual, GstValueExt, ObjectExt, StaticType};
+use gstreamer::{CapsRef, DeviceMonitor, StructureRef};
+use log::debug;
+
+use crate::{DeviceInfo, DeviceMode};
+
+use super::Error;
+use crate::Error as VideoError;
+
+/// Keys under which device providers publish the device node, in order of preference
+const PATH_PROPERTIES: [&str; 3] = ["api.v4l2.path", "device.path", "object.path"];
+
+/// Serialize the field `name` of a caps structure, whatever its type (value, range or list)
+fn field_string(s: &StructureRef, name: &str) -> Option<String> {
+    s.value(name)
+        .ok()
+        .and_then(|v| v.serialize().ok())
+        .map(|v| v.to_string())
+}
+
+/// Describe one caps structure of a device as a capture mode
+fn device_mode(s: &StructureRef) -> DeviceMode {
+    let format = match s.name().as_str() {
+        "video/x-raw" => match field_string(s, "format") {
+            Some(f) => format!("raw {}", f),
+            None => "raw".to_string(),
+        },
+        "image/jpeg" => "mjpeg".to_string(),
+        "video/x-h264" => "h264".to_string(),
+        other => other.to_string(),
+    };
+    let resolution = match (field_string(s, "width"), field_string(s, "height")) {
+        (Some(w), Some(h)) => format!("{}x{}", w, h),
+        _ => "any".to_string(),
+    };
+    DeviceMode {
+        format,
+        resolution,
+        framerates: field_string(s, "framerate").unwrap_or_else(|| "any".to_string()),
+    }
+}
+
+/// Describe each caps structure of a device as a capture mode
+fn device_modes(caps: &CapsRef) -> Vec<DeviceMode> {
+    caps.iter().map(device_mode).collect()
+}
+
+/// Find the device node (e.g. `/dev/video0`) of a device
+fn device_path(device: &gstreamer::Device) -> Option<String> {
+    if let Some(props) = device.properties() {
+        for key in PATH_PROPERTIES {
+            if let Ok(path) = props.get::<String>(key) {
+                return Some(path);
+            }
+        }
+    }
+    // Fall back to the source element the device would create
+    device
+        .create_element(None)
+        .ok()
+        .filter(|elem| elem.has_property("device", Some(String::static_type())))
+        .map(|elem| elem.property::<String>("device"))
+}
+
+/// List the video capture devices and their capture modes, using a
+/// [device monitor](https://gstreamer.freedesktop.org/documentation/gstreamer/gstdevicemonitor.html?gi-language=c)
+pub fn list_devices() -> Result<Vec<DeviceInfo>, VideoError> {
+    gstreamer::init().map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+
+    let monitor = DeviceMonitor::new();
+    monitor.add_filter(Some("Video/Source"), None);
+
+    let devices = monitor
+        .devices()
+        .iter()
+        .map(|device| {
+            debug!(
+                "Found {} ({})",
+                device.display_name(),
+                device.device_class()
+            );
+            DeviceInfo {
+                name: device.display_name().to_string(),
+                path: device_path(device),
+                modes: device
+                    .caps()
+                    .map(|caps| device_modes(&caps))
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn caps_structures_are_capture_modes() {
+        gstreamer::init().unwrap();
+        // What videotestsrc offers, then what a v4l2 webcam typically does
+        let caps = gstreamer::Caps::from_str(
+            "video/x-raw, format=(string){ I420, YUY2 }, width=(int)[ 1, 2147483647 ], \
+             height=(int)[ 1, 2147483647 ], framerate=(fraction)[ 0/1, 2147483647/1 ]; \
+             image/jpeg, width=(int)1280, height=(int)720, framerate=(fraction){ 30/1, 15/1 }; \
+             video/x-h264, stream-format=(string)byte-stream",
+        )
+        .unwrap();
+        let device = DeviceInfo {
+            name: "Test Camera".to_string(),
+            path: Some("/dev/video0".to_string()),
+            modes: device_modes(&caps),
+        };
+        assert_eq!(
+            device.to_string().lines().collect::<Vec<_>>(),
+            [
+                "/dev/video0: Test Camera",
+                "  raw { (string)I420, (string)YUY2 } [ 1, 2147483647 ]x[ 1, 2147483647 ] @ [ 0/1, 2147483647/1 ]",
+                "  mjpeg 1280x720 @ { (fraction)30/1, (fraction)15/1 }",
+                "  h264 any @ any",
+            ]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use gstreamer::prelude::{Cast, ElementExt};
+use gstreamer::{BufferFlags, Caps, ClockTime, Element, FlowError, FlowSuccess, Sample, Structure};
+use gstreamer_app::{AppSink, AppSinkCallbacks};
+use log::{debug, error};
+
+use util::{expand_frame_pattern, FrameOptions, FrameSelection, ImageFormat};
+
+/// Name of the element message posted when a frame is written to an image file
+pub(crate) const FRAME_EXTRACTED_MESSAGE: &str = "frame-extracted";
+
+/// How long the conversion of a frame to an image may take
+pub(crate) const CONVERT_TIMEOUT: ClockTime = ClockTime::from_seconds(5);
+
+/// Caps of the images in `format`, to convert frames to
+pub(crate) fn image_caps(format: ImageFormat) -> Caps {
+    Caps::builder(match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+    })
+    .build()
+}
+
+/// Picks the frames to extract, as they come, according to a [`FrameSelection`]
+struct FrameSelector {
+    selection: FrameSelection,
+    /// Number of the next frame to extract, with [`FrameSelection::Every`]
+    next_frame: u64,
+    /// Timestamp of the next frame to extract, with [`FrameSelection::Interval`]
+    next: ClockTime,
+    /// Timestamps left to extract, with [`FrameSelection::Timestamps`], in order
+    timestamps: Vec<ClockTime>,
+}
+
+impl FrameSelector {
+    fn new(selection: &FrameSelection) -> Self {
+        let mut timestamps = match selection {
+            FrameSelection::Timestamps(secs) => secs
+                .iter()
+                .map(|secs| ClockTime::from_seconds_f64(*secs))
+                .collect(),
+            _ => vec![],
+        };
+        // Popped from the end
+        timestamps.sort_by(|a, b| b.cmp(a));
+        FrameSelector {
+            selection: selection.clone(),
+            next_frame: 0,
+            next: ClockTime::ZERO,
+            timestamps,
+        }
+    }
+
+    /// Whether frame number `frame`, at `timestamp` from the first frame, is extracted
+    fn select(&mut self, frame: u64, timestamp: ClockTime, keyframe: bool) -> bool {
+        match &self.selection {
+            FrameSelection::Every(n) => {
+                let selected = frame >= self.next_frame;
+                if selected {
+                    self.next_frame = frame + n;
+                }
+                selected
+            }
+            FrameSelection::Interval(secs) => {
+                let selected = timestamp >= self.next;
+                if selected {
+                    let interval = ClockTime::from_seconds_f64(*secs);
+                    while self.next <= timestamp && !interval.is_zero() {
+                        self.next += interval;
+                    }
+                }
+                selected
+            }
+            FrameSelection::Timestamps(_) => {
+                let mut selected = false;
+                while self.timestamps.last().is_some_and(|at| *at <= timestamp) {
+                    self.timestamps.pop();
+                    selected = true;
+                }
+                selected
+            }
+            FrameSelection::Keyframes => keyframe,
+        }
+    }
+
+    /// Whether no frame is left to extract: all the listed timestamps are
+    fn is_done(&self) -> bool {
+        matches!(self.selection, FrameSelection::Timestamps(_)) && self.timestamps.is_empty()
+    }
+}
+
+/// Writes the frames selected by [`FrameSelection`], from an `appsink`, to image files named
+/// after the [pattern](util::FrameOptions::pattern).
+///
+/// Frames are numbered from 0 and timestamped from the first frame, whatever the running time
+/// of the pipeline.
+pub(crate) struct FrameExtractor {
+    pattern: PathBuf,
+    format: ImageFormat,
+    selector: FrameSelector,
+    /// Number of the next frame
+    frame: u64,
+    /// Timestamp of the first frame
+    first: Option<ClockTime>,
+    /// The appsink feeding the extractor, where images are announced
+    sink: Element,
+}
+
+impl FrameExtractor {
+    /// Extract frames from the samples of `sink`
+    pub(crate) fn attach(sink: &AppSink, frames: &FrameOptions) {
+        let extractor = Arc::new(Mutex::new(FrameExtractor {
+            pattern: frames.pattern.clone(),
+            format: frames.format,
+            selector: FrameSelector::new(&frames.selection),
+            frame: 0,
+            first: None,
+            sink: sink.clone().upcast(),
+        }));
+
+        sink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                    match extractor.lock() {
+                        Ok(mut extractor) => extractor.push(&sample),
+                        Err(_) => Err(FlowError::Error),
+                    }
+                })
+                .build(),
+        );
+    }
+
+    /// Extract the frame in `sample` if it is selected.
+    /// Returns EOS once all the listed timestamps are extracted, which ends the stream.
+    fn push(&mut self, sample: &Sample) -> Result<FlowSuccess, FlowError> {
+        let Some(buffer) = sample.buffer() else {
+            return Ok(FlowSuccess::Ok);
+        };
+        let pts = buffer.pts().unwrap_or(ClockTime::ZERO);
+        let timestamp = pts.saturating_sub(*self.first.get_or_insert(pts));
+        let frame = self.frame;
+        self.frame += 1;
+
+        let keyframe = !buffer.flags().contains(BufferFlags::DELTA_UNIT);
+        if self.selector.select(frame, timestamp, keyframe) {
+            self.write(sample, frame, timestamp)?;
+        }
+
+        if self.selector.is_done() {
+            debug!("All the frames are extracted");
+            return Err(FlowError::Eos);
+        }
+        Ok(FlowSuccess::Ok)
+    }
+
+    /// Convert the frame to an image and write it to the file named after the pattern
+    fn write(&self, sample: &Sample, frame: u64, timestamp: ClockTime) -> Result<(), FlowError> {
+        let image =
+            gstreamer_video::convert_sample(sample, &image_caps(self.format), CONVERT_TIMEOUT)
+                .map_err(|e| {
+                    error!("Can't convert frame {}: {}", frame, e);
+                    FlowError::Error
+                })?;
+        let buffer = image
+            .buffer()
+            .and_then(|buf| buf.map_readable().ok())
+            .ok_or(FlowError::Error)?;
+
+        let name = self
+            .pattern
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path =
+            self.pattern
+                .with_file_name(expand_frame_pattern(&name, frame, timestamp.mseconds()));
+        if let Err(e) = fs::write(&path, buffer.as_slice()) {
+            gstreamer::element_error!(
+                self.sink,
+                gstreamer::ResourceError::Write,
+                ("Can't write {}: {}", path.display(), e)
+            );
+            return Err(FlowError::Error);
+        }
+
+        let structure = Structure::builder(FRAME_EXTRACTED_MESSAGE)
+            .field("location", path.to_string_lossy().to_string())
+            .field("timestamp", timestamp.nseconds())
+            .build();
+        let _ = self.sink.post_message(
+            gstreamer::message::Element::builder(structure)
+                .src(&self.sink)
+                .build(),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers of the frames selected among `count` frames at 25 fps, the keyframes every 10
+    fn selected(selection: FrameSelection, count: u64) -> Vec<u64> {
+        let mut selector = FrameSelector::new(&selection);
+        let keyframes: Vec<u64> = (0..count).step_by(10).collect();
+        (0..count)
+            .filter(|frame| {
+                let timestamp = ClockTime::from_mseconds(frame * 40);
+                selector.select(*frame, timestamp, keyframes.contains(frame))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_nth_frame_from_the_first() {
+        assert_eq!(selected(FrameSelection::Every(10), 25), vec![0, 10, 20]);
+        assert_eq!(selected(FrameSelection::Every(1), 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn first_frame_of_every_interval() {
+        assert_eq!(selected(FrameSelection::Interval(1.0), 75), vec![0, 25, 50]);
+        // Shorter than a frame: every frame, once
+        assert_eq!(selected(FrameSelection::Interval(0.01), 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn first_frame_at_or_after_each_timestamp() {
+        // 0.5 s falls between frames 12 and 13; the timestamps are sorted
+        assert_eq!(
+            selected(FrameSelection::Timestamps(vec![1.01, 0.5, 1.0]), 50),
+            vec![13, 25, 26]
+        );
+    }
+
+    #[test]
+    fn done_once_all_the_timestamps_are_extracted() {
+        let mut selector = FrameSelector::new(&FrameSelection::Timestamps(vec![0.0, 0.04]));
+        assert!(selector.select(0, ClockTime::ZERO, true));
+        assert!(!selector.is_done());
+        assert!(selector.select(1, ClockTime::from_mseconds(40), false));
+        assert!(selector.is_done());
+        assert!(!FrameSelector::new(&FrameSelection::Every(1)).is_done());
+    }
+
+    #[test]
+    fn keyframes_only() {
+        assert_eq!(selected(FrameSelection::Keyframes, 25), vec![0, 10, 20]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use gstreamer::prelude::{GstBinExtManual, GstObjectExt};
+use gstreamer::{DebugGraphDetails, Pipeline};
+use log::{debug, warn};
+
+use util::GraphFormat;
+
+/// Writes [Graphviz](https://graphviz.org/) DOT snapshots of a pipeline to a directory.
+///
+/// Files are numbered in the order they are written, so the directory listing reads as
+/// the pipeline's history, e.g. `003-hc-pipeline.paused_playing.dot`.
+pub(crate) struct GraphDumper {
+    dir: PathBuf,
+    format: Option<GraphFormat>,
+    count: u32,
+}
+
+impl GraphDumper {
+    pub(crate) fn new(dir: PathBuf, format: Option<GraphFormat>) -> Self {
+        GraphDumper {
+            dir,
+            format,
+            count: 0,
+        }
+    }
+
+    /// Dump the current state of `pipeline`, including the negotiated caps, as `<label>`.
+    /// Failures are logged but never interrupt the pipeline.
+    pub(crate) fn dump(&mut self, pipeline: &Pipeline, label: &str) {
+        let dot = pipeline.debug_to_dot_data(DebugGraphDetails::all());
+        let path = self.dir.join(format!(
+            "{:03}-{}.{}.dot",
+            self.count,
+            pipeline.name(),
+            label
+        ));
+        self.count += 1;
+
+        if let Err(e) = fs::write(&path, dot.as_str()) {
+            warn!("Can't write pipeline graph {}: {}", path.display(), e);
+            return;
+        }
+        debug!("Pipeline graph written to {}", path.display());
+
+        if let Some(format) = self.format {
+            let out = path.with_extension(format.to_string());
+            match Command::new("dot")
+                .arg(format!("-T{}", format))
+                .arg(&path)
+                .arg("-o")
+                .arg(&out)
+                .status()
+            {
+                Ok(status) if status.success() => {
+                    debug!("Pipeline graph rendered to {}", out.display())
+                }
+                Ok(status) => warn!("dot failed to render {}: {}", path.display(), status),
+                Err(e) => {
+                    warn!("Can't run dot, graphs will not be rendered: {}", e);
+                    self.format = None;
+                }
+            }
+        }
+    }
+}
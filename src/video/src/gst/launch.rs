@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use gstreamer::glib::Object;
+use gstreamer::glib::ParamFlags;
+use gstreamer::prelude::{Cast, ElementExt, GstObjectExt, GstValueExt, ObjectExt};
+use gstreamer::Element;
+
+use super::SourceBranch;
+
+/// Properties that are never part of a launch line: the name is assigned by gst-launch
+/// itself, the parent is implied by the position in the pipeline and the template of a
+/// requested pad by the pad's name
+const SKIPPED_PROPERTIES: [&str; 3] = ["name", "parent", "template"];
+
+/// Quote a token so that the shell passes it to `gst-launch-1.0` verbatim
+fn quote(token: &str) -> String {
+    let plain = token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_=./:,+@".contains(c));
+    if plain {
+        token.to_string()
+    } else {
+        format!("'{}'", token.replace('\'', r"'\''"))
+    }
+}
+
+/// Describe a single element the way `gst-launch-1.0` expects it:
+/// the factory name followed by the properties that differ from their defaults.
+/// Capsfilters are described by their caps alone, which is the launch line shorthand.
+/// Tokens are passed through `quote`.
+fn describe_element(elem: &Element, quote: fn(&str) -> String) -> String {
+    let factory = elem
+        .factory()
+        .map(|f| f.name().to_string())
+        .unwrap_or_default();
+
+    if factory == "capsfilter" {
+        if let Some(caps) = elem.property::<Option<gstreamer::Caps>>("caps") {
+            return quote(&caps.to_string());
+        }
+    }
+
+    let mut tokens = vec![factory];
+    tokens.extend(
+        properties(elem.upcast_ref(), ParamFlags::empty())
+            .iter()
+            .map(|property| quote(property)),
+    );
+    tokens.join(" ")
+}
+
+/// The `name=value` pairs of the writable properties of `obj` that differ from their defaults,
+/// leaving out those with any of the `skipped` flags
+fn properties(obj: &Object, skipped: ParamFlags) -> Vec<String> {
+    let mut pairs = vec![];
+    for pspec in obj.list_properties().iter() {
+        let flags = pspec.flags();
+        if !flags.contains(ParamFlags::READWRITE)
+            || flags.intersects(skipped)
+            || SKIPPED_PROPERTIES.contains(&pspec.name())
+        {
+            continue;
+        }
+
+        let value = obj.property_value(pspec.name());
+        if value.compare(pspec.default_value()) == Some(Ordering::Equal) {
+            continue;
+        }
+        if let Ok(serialized) = value.serialize() {
+            pairs.push(format!("{}={}", pspec.name(), serialized));
+        }
+    }
+    pairs
+}
+
+/// Describe a linear chain of elements, each token passed through `quote`.
+/// Bins parsed from a launch description are given back as `fragments` (bin name to
+/// description) and are inlined verbatim.
+fn chain(
+    steps: &[Element],
+    fragments: &HashMap<String, String>,
+    quote: fn(&str) -> String,
+) -> String {
+    let elements: Vec<String> = steps
+        .iter()
+        .map(|elem| match fragments.get(elem.name().as_str()) {
+            Some(desc) => desc.clone(),
+            None => describe_element(elem, quote),
+        })
+        .collect();
+    elements.join(" ! ")
+}
+
+/// Describe a chain of elements whose first element is fed by the chains in `inputs` (e.g. a
+/// concat or a compositor) and whose last element (a tee) feeds `branches`, each token passed
+/// through `quote`. The properties of the pads requested for the inputs follow the first
+/// element, as `pad::name=value`.
+fn tree(
+    inputs: &[SourceBranch],
+    steps: &[Element],
+    branches: &[Vec<Element>],
+    fragments: &HashMap<String, String>,
+    quote: fn(&str) -> String,
+) -> String {
+    let mut line = String::new();
+    match steps.split_first().filter(|_| !inputs.is_empty()) {
+        Some((first, rest)) => {
+            for input in inputs {
+                line.push_str(&format!(
+                    "{} ! {}.{}  ",
+                    chain(&input.steps, fragments, quote),
+                    first.name(),
+                    input.pad.name()
+                ));
+            }
+            line.push_str(&format!(
+                "{} name={}",
+                chain(std::slice::from_ref(first), fragments, quote),
+                first.name()
+            ));
+            for input in inputs {
+                // The direction of a pad is fixed when it is requested
+                for property in properties(input.pad.upcast_ref(), ParamFlags::CONSTRUCT_ONLY) {
+                    line.push_str(&format!(
+                        " {}",
+                        quote(&format!("{}::{}", input.pad.name(), property))
+                    ));
+                }
+            }
+            if !rest.is_empty() {
+                line.push_str(&format!(" ! {}", chain(rest, fragments, quote)));
+            }
+        }
+        None => line.push_str(&chain(steps, fragments, quote)),
+    }
+    if let Some(tee) = steps.last().filter(|_| !branches.is_empty()) {
+        line.push_str(&format!(" name={}", tee.name()));
+        for branch in branches {
+            line.push_str(&format!(
+                "  {}. ! {}",
+                tee.name(),
+                chain(branch, fragments, quote)
+            ));
+        }
+    }
+    line
+}
+
+/// Build the `gst-launch-1.0` command line equivalent to a chain of elements, optionally fed by
+/// the chains in `inputs` and ending with a tee that feeds `branches`
+pub(crate) fn launch_line(
+    inputs: &[SourceBranch],
+    steps: &[Element],
+    branches: &[Vec<Element>],
+    fragments: &HashMap<String, String>,
+) -> String {
+    format!(
+        "gst-launch-1.0 {}",
+        tree(inputs, steps, branches, fragments, quote)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use gstreamer::prelude::*;
+
+    use super::super::registry::ElementRegistry;
+    use super::*;
+
+    fn make(names: &mut ElementRegistry, factory: &str) -> Element {
+        gstreamer::init().unwrap();
+        names.make(factory).build().unwrap()
+    }
+
+    #[test]
+    fn elements_show_the_properties_that_differ_from_defaults() {
+        let mut names = ElementRegistry::default();
+        let queue = make(&mut names, "queue");
+        queue.set_property("max-size-buffers", 5u32);
+        queue.set_property_from_str("leaky", "downstream");
+        let identity = make(&mut names, "identity");
+        assert_eq!(
+            describe_element(&queue, quote),
+            "queue max-size-buffers=5 leaky=downstream"
+        );
+        assert_eq!(describe_element(&identity, quote), "identity");
+    }
+
+    #[test]
+    fn capsfilters_are_shown_as_caps() {
+        let mut names = ElementRegistry::default();
+        let caps = make(&mut names, "capsfilter");
+        caps.set_property(
+            "caps",
+            gstreamer::Caps::builder("video/x-raw")
+                .field("width", 640)
+                .field("height", 480)
+                .build(),
+        );
+        assert_eq!(
+            describe_element(&caps, quote),
+            "'video/x-raw, width=(int)640, height=(int)480'"
+        );
+    }
+
+    #[test]
+    fn tokens_are_quoted_for_the_shell() {
+        assert_eq!(quote("location=/tmp/out.mp4"), "location=/tmp/out.mp4");
+        assert_eq!(quote("text=two words"), "'text=two words'");
+        assert_eq!(quote("text=it's"), r"'text=it'\''s'");
+    }
+
+    #[test]
+    fn fragments_are_inlined() {
+        let mut names = ElementRegistry::default();
+        let fragment = "identity drop-probability=0.5 ! identity";
+        let bin = gstreamer::parse::bin_from_description_with_name(fragment, true, "filterbin-0")
+            .unwrap();
+        let steps = [make(&mut names, "identity"), bin.upcast()];
+        let fragments = HashMap::from([("filterbin-0".to_string(), fragment.to_string())]);
+        assert_eq!(
+            launch_line(&[], &steps, &[], &fragments),
+            "gst-launch-1.0 identity ! identity drop-probability=0.5 ! identity"
+        );
+    }
+
+    #[test]
+    fn demuxers_are_chained_like_other_elements() {
+        // gst-launch links the pads that a demuxer adds while it runs, as build() does
+        let mut names = ElementRegistry::default();
+        let steps = [
+            make(&mut names, "identity"),
+            make(&mut names, "streamiddemux"),
+            make(&mut names, "identity"),
+        ];
+        assert_eq!(
+            launch_line(&[], &steps, &[], &HashMap::new()),
+            "gst-launch-1.0 identity ! streamiddemux ! identity"
+        );
+    }
+
+    #[test]
+    fn inputs_are_linked_to_the_pads_requested_for_them() {
+        let mut names = ElementRegistry::default();
+        let selector = make(&mut names, "input-selector");
+        let inputs: Vec<SourceBranch> = (0..2u32)
+            .map(|i| {
+                let pad = selector.request_pad_simple("sink_%u").unwrap();
+                pad.set_property("always-ok", i == 0);
+                let queue = make(&mut names, "queue");
+                queue.set_property("max-size-buffers", 10 * (i + 1));
+                SourceBranch {
+                    steps: vec![queue],
+                    pad,
+                }
+            })
+            .collect();
+        let steps = [selector, make(&mut names, "identity")];
+        assert_eq!(
+            launch_line(&inputs, &steps, &[], &HashMap::new()),
+            "gst-launch-1.0 queue max-size-buffers=10 ! input-selector-0.sink_0  \
+             queue max-size-buffers=20 ! input-selector-0.sink_1  \
+             input-selector name=input-selector-0 sink_1::always-ok=false ! identity"
+        );
+    }
+
+    #[test]
+    fn tees_feed_their_branches() {
+        let mut names = ElementRegistry::default();
+        let steps = [make(&mut names, "identity"), make(&mut names, "tee")];
+        let branches: Vec<Vec<Element>> = (0..2)
+            .map(|_| vec![make(&mut names, "queue"), make(&mut names, "identity")])
+            .collect();
+        assert_eq!(
+            launch_line(&[], &steps, &branches, &HashMap::new()),
+            "gst-launch-1.0 identity ! tee name=tee-0  tee-0. ! queue ! identity  \
+             tee-0. ! queue ! identity"
+        );
+    }
+}
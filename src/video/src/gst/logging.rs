@@ -0,0 +1,71 @@
+use gstreamer::DebugLevel;
+use log::{Level, LevelFilter, Record};
+
+use super::Error;
+use crate::Error as VideoError;
+
+/// Map a [`log`] level filter onto the equivalent gstreamer debug threshold
+fn debug_threshold(level: LevelFilter) -> DebugLevel {
+    match level {
+        LevelFilter::Off => DebugLevel::None,
+        LevelFilter::Error => DebugLevel::Error,
+        LevelFilter::Warn => DebugLevel::Warning,
+        LevelFilter::Info => DebugLevel::Info,
+        LevelFilter::Debug => DebugLevel::Debug,
+        LevelFilter::Trace => DebugLevel::Trace,
+    }
+}
+
+/// Map a gstreamer debug level onto a [`log`] level.
+/// `FIXME` messages are reported as warnings, everything below `DEBUG` as trace.
+fn log_level(level: DebugLevel) -> Option<Level> {
+    match level {
+        DebugLevel::None => None,
+        DebugLevel::Error => Some(Level::Error),
+        DebugLevel::Warning | DebugLevel::Fixme => Some(Level::Warn),
+        DebugLevel::Info => Some(Level::Info),
+        DebugLevel::Debug => Some(Level::Debug),
+        _ => Some(Level::Trace),
+    }
+}
+
+/// Initialize gstreamer and redirect its debug output to the [`log`] facade, so that
+/// gstreamer's messages end up in the same sink as ours.
+///
+/// The gstreamer debug threshold follows `level`, unless overridden by `GST_DEBUG`.
+/// Messages are logged with the `gst::<category>` target, e.g. `gst::videoconvert`.
+pub fn init_logging(level: LevelFilter) -> Result<(), VideoError> {
+    gstreamer::init().map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+
+    gstreamer::log::remove_default_log_function();
+    gstreamer::log::add_log_function(|category, level, file, function, line, object, msg| {
+        let Some(level) = log_level(level).filter(|l| *l <= log::max_level()) else {
+            return;
+        };
+        let Some(msg) = msg.get() else {
+            return;
+        };
+        let target = format!("gst::{}", category.name());
+        let args = match object {
+            Some(obj) => format!("<{}> {}", obj, msg),
+            None => msg.to_string(),
+        };
+        log::logger().log(
+            &Record::builder()
+                .level(level)
+                .target(&target)
+                .module_path(Some(function.as_str()))
+                .file(Some(file.as_str()))
+                .line(Some(line))
+                .args(format_args!("{}", args))
+                .build(),
+        );
+    });
+
+    if std::env::var_os("GST_DEBUG").is_none() {
+        gstreamer::log::set_default_threshold(debug_threshold(level));
+    }
+    gstreamer::log::set_active(level != LevelFilter::Off);
+
+    Ok(())
+}
@@ -1,15 +1,64 @@
+use std::collections::HashMap;
 use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use gstreamer::prelude::{ElementExt, ElementExtManual, GstBinExtManual, GstObjectExt, PadExt};
-use gstreamer::{glib, Element, ElementFactory, Pipeline};
+use gstreamer::prelude::{
+    Cast, Displayable, ElementExt, ElementExtManual, GObjectExtManualGst, GstBinExt,
+    GstBinExtManual, GstObjectExt, ObjectExt, PadExt,
+};
+use gstreamer::{glib, ClockTime, Element, PadDirection, PadPresence, Pipeline};
+use log::{debug, error, info, warn};
 
-use util::{DecoderOptions, VideoFormat};
+use util::{
+    DecoderOptions, ImageFormat, ImageSequenceOptions, NetworkOptions, Output, PixelFormat,
+    RawOptions, TestPatternOptions, VideoFormat, WebcamOptions,
+};
 
-use crate::VideoInput;
+use crate::{Event, VideoInput};
+
+use graph::GraphDumper;
+use registry::ElementRegistry;
+pub use ringbuffer::Trigger;
+use ringbuffer::{EventRecorders, RingBuffer};
 
 use super::Error as VideoError;
 
+/// Source steps composing several inputs into one picture
+mod composite;
+/// Contact sheets of video files
+pub mod contactsheet;
+/// Capture device discovery
+pub mod devices;
+/// Frame extraction to image files
+mod frames;
+/// DOT graph snapshots of the pipeline
+mod graph;
+/// `gst-launch-1.0` equivalent of a built pipeline
+mod launch;
+/// Bridge between gstreamer's debug output and the [`log`] facade
+pub mod logging;
+/// Headerless raw video file output
+mod rawfile;
+/// Unique element names and element lookup by role
+mod registry;
+/// In-memory pre-event buffer of the event recording outputs
+mod ringbuffer;
+/// Built-in RTSP server output
+mod rtsp;
+/// Output steps
+mod sink;
+/// Files joined with transitions, on a GES timeline
+pub mod timeline;
+
+/// Callback registered with [`on_event`](crate::Decoder::on_event)
+type EventHandler = Box<dyn Fn(&Event) + Send>;
+
 #[derive(Debug)]
 /// Gstreamer errors
 pub enum Error {
@@ -21,6 +70,32 @@ pub enum Error {
     PipelineStateChange(gstreamer::StateChangeError),
     /// gstreamer pipeline doesn't have a message bus
     Bus,
+    /// A custom filter bin description can't be parsed into a bin
+    FilterBinParse(String, glib::Error),
+    /// A custom filter bin doesn't expose exactly one sink and one src pad
+    FilterBinPads(String),
+    /// Two consecutive elements of the pipeline can't be linked
+    Link(String, String, glib::BoolError),
+    /// The capture device can't be opened
+    DeviceUnavailable(String),
+    /// The capture device can't provide the requested caps (device, requested, available)
+    DeviceCaps(String, String, String),
+    /// The RTSP server can't be set up
+    RtspServer(String),
+    /// A contact sheet can't be made
+    ContactSheet(String),
+    /// A timeline of files joined with transitions can't be built or played
+    Timeline(String),
+    /// An input file has no video stream
+    NoVideo(String),
+    /// An element mixing or sequencing several inputs can't give one more sink pad
+    RequestPad(String),
+    /// Several inputs can't be composed into one picture
+    Composite(String),
+    /// An element that isn't part of the installed plugins is needed (element, plugin)
+    MissingElement(String, String),
+    /// The pipeline stopped on an error it can't recover from
+    Pipeline(String),
 }
 
 impl Display for Error {
@@ -30,15 +105,157 @@ impl Display for Error {
             Error::GlibBool(e) => write!(f, "glib bool error: {}", e),
             Error::PipelineStateChange(e) => write!(f, "pipeline state change error: {}", e),
             Error::Bus => write!(f, "pipeline without bus"),
+            Error::FilterBinParse(desc, e) => {
+                write!(f, "can't parse filter bin \"{}\": {}", desc, e)
+            }
+            Error::FilterBinPads(desc) => write!(
+                f,
+                "filter bin \"{}\" must have exactly one unlinked sink pad and one unlinked src pad",
+                desc
+            ),
+            Error::Link(src, sink, e) => write!(f, "can't link {} to {}: {}", src, sink, e),
+            Error::DeviceUnavailable(dev) => write!(f, "can't open capture device {}", dev),
+            Error::DeviceCaps(dev, requested, available) => write!(
+                f,
+                "capture device {} can't provide {}; supported: {}",
+                dev, requested, available
+            ),
+            Error::RtspServer(e) => write!(f, "RTSP server error: {}", e),
+            Error::ContactSheet(e) => write!(f, "can't make the contact sheet: {}", e),
+            Error::NoVideo(fname) => write!(f, "{} has no video stream", fname),
+            Error::RequestPad(elem) => write!(f, "{} has no free sink pad", elem),
+            Error::Composite(e) => write!(f, "can't compose the inputs: {}", e),
+            Error::MissingElement(factory, plugin) => write!(
+                f,
+                "the {} element isn't installed, it comes with {}",
+                factory, plugin
+            ),
+            Error::Timeline(e) => write!(f, "timeline error: {}", e),
+            Error::Pipeline(e) => write!(f, "pipeline error: {}", e),
+        }
+    }
+}
+
+/// Something written to disk when the pipeline starts rather than while it is built, so that
+/// a dry run leaves no trace
+pub(crate) enum Preparation {
+    /// A directory, created along with its parents if missing, that must be writable
+    Dir(PathBuf),
+    /// A file and its contents
+    File(PathBuf, String),
+}
+
+impl Preparation {
+    pub(crate) fn apply(&self) -> Result<(), VideoError> {
+        match self {
+            Preparation::Dir(dir) => {
+                debug!("Creating directory {}", dir.display());
+                fs::create_dir_all(dir).map_err(VideoError::Io)?;
+                // Permission bits don't tell whether this process may write there, a file does
+                let probe = dir.join(format!(".harmanchallenge-{}", process::id()));
+                fs::File::create(&probe)
+                    .and_then(|_| fs::remove_file(&probe))
+                    .map_err(|e| {
+                        VideoError::Io(io::Error::new(
+                            e.kind(),
+                            format!("{} is not writable: {}", dir.display(), e),
+                        ))
+                    })
+            }
+            Preparation::File(path, contents) => {
+                fs::write(path, contents).map_err(VideoError::Io)?;
+                info!("{} written", path.display());
+                Ok(())
+            }
         }
     }
 }
 
+/// Check up front that the `factory` element, from `plugin`, is installed, rather than
+/// failing with a generic error once the pipeline is built
+pub(crate) fn require_element(factory: &str, plugin: &str) -> Result<(), VideoError> {
+    match gstreamer::ElementFactory::find(factory) {
+        Some(_) => Ok(()),
+        None => Err(VideoError::Gstreamer(Error::MissingElement(
+            factory.to_string(),
+            plugin.to_string(),
+        ))),
+    }
+}
+
+/// The video of a file, as probed before the pipeline is built
+pub(crate) struct Clip {
+    /// Resolution (width x height)
+    pub(crate) width_height: (i32, i32),
+    /// Framerate; variable framerates are taken as 25 fps
+    pub(crate) framerate: gstreamer::Fraction,
+    /// Duration, if the container tells
+    pub(crate) duration: Option<ClockTime>,
+}
+
+/// A chain of source elements feeding a request pad of the first source step, which sequences
+/// or mixes several inputs (a concat or a compositor)
+pub(crate) struct SourceBranch {
+    pub(crate) steps: Vec<Element>,
+    /// The sink pad of the first source step that the last element of the chain is linked to
+    pub(crate) pad: gstreamer::Pad,
+}
+
+/// Ends a running pipeline cleanly: an end of stream goes through it, so that the outputs are
+/// finalized (e.g. the mp4 index is written), the way `gst-launch-1.0 -e` does on Ctrl-C.
+///
+/// Cheap to clone and usable from any thread, e.g. by a signal handler.
+#[derive(Clone)]
+pub struct Interrupt(Pipeline);
+
+impl Interrupt {
+    /// Send an end of stream through the pipeline; `run()` returns once the outputs got it
+    pub fn finish(&self) {
+        info!("Finishing the outputs");
+        self.0.send_event(gstreamer::event::Eos::new());
+    }
+}
+
 /// Struct that implements the [`Decoder`](crate::Decoder) trait using gstreamer as a backend
 pub struct GstreamerDecoder {
+    /// The input, kept to build the same elements again for each RTSP media
+    input: VideoInput,
+    /// The options the pipeline was built with, kept for the same reason if it is served over
+    /// RTSP
+    options: Option<DecoderOptions>,
     srcsteps: Vec<Element>,
-    sinksteps: Vec<Element>,
+    /// One chain of elements per input, feeding the first source step, if there are several
+    /// inputs (files played in a row, or composed into one picture)
+    source_branches: Vec<SourceBranch>,
+    /// Resolution and framerate the files played in a row are converted to, if there are
+    /// several input files
+    clip_format: Option<((i32, i32), gstreamer::Fraction)>,
+    /// The elements of the pipeline up to the outputs (or the tee feeding them),
+    /// in linking order, once built
+    steps: Vec<Element>,
+    /// One chain of elements per output, fed by the last step, if there are several outputs
+    branches: Vec<Vec<Element>>,
     pipeline: Pipeline,
+    /// The bin the elements are added to: the pipeline, or the bin of an RTSP media
+    bin: gstreamer::Bin,
+    /// Writes DOT graphs of the pipeline while it runs, if requested
+    graph: Option<GraphDumper>,
+    /// Launch descriptions of the custom filter bins, by bin name
+    fragments: HashMap<String, String>,
+    /// Element names and roles
+    names: ElementRegistry,
+    /// Reconnection policy, for network input
+    reconnect: Option<NetworkOptions>,
+    /// Where the processed video goes, once built
+    outputs: Vec<Output>,
+    /// Called with each [`Event`] while the pipeline runs
+    event_handlers: Vec<EventHandler>,
+    /// Ring buffers of the event recording outputs
+    recorders: EventRecorders,
+    /// Part of the input to process (start, and stop if any), if not all of it
+    range: Option<(ClockTime, Option<ClockTime>)>,
+    /// What to write to disk before the pipeline starts, once built
+    preparations: Vec<Preparation>,
 }
 
 impl GstreamerDecoder {
@@ -48,59 +265,504 @@ impl GstreamerDecoder {
     /// 1. [`h264`` decoder](https://gstreamer.freedesktop.org/documentation/libav/avdec_h264.html?gi-language=c#avdec_h264-page) for the demux'ed video stream
     /// 1. [video converter](https:/)/gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page) to automatically convert the video stream into a format
     ///    compatible with whatever comes next in the pipeline
-    fn filesource(infname: String) -> Result<Vec<Element>, VideoError> {
+    fn filesource(
+        names: &mut ElementRegistry,
+        infname: String,
+    ) -> Result<Vec<Element>, VideoError> {
         Ok(vec![
-            ElementFactory::make("filesrc")
+            names
+                .make_with_role("filesrc", "source")
                 .property_from_str("location", infname.as_str())
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("qtdemux")
-                .name("demux")
+            names
+                .make_with_role("qtdemux", "demuxer")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("avdec_h264")
-                .name("avdec_h264-0")
+            names
+                .make_with_role("avdec_h264", "decoder")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("videoconvert")
-                .name("videoconvert0")
+            names
+                .make("videoconvert")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
         ])
     }
 
-    fn webcamsource() -> Result<Vec<Element>, VideoError> {
-        // v4l2src ! videoconvert !
+    /// Create the first steps of the pipeline for capturing from a webcam:
+    /// 1. [`v4l2src`](https://gstreamer.freedesktop.org/documentation/video4linux2/v4l2src.html?gi-language=c) reading from the requested device
+    /// 1. [`capsfilter`](https://gstreamer.freedesktop.org/documentation/coreelements/capsfilter.html?gi-language=c#capsfilter-page) with the requested resolution, framerate and format, if any
+    /// 1. for compressed formats, a decoder: [`jpegdec`](https://gstreamer.freedesktop.org/documentation/jpeg/jpegdec.html?gi-language=c)
+    ///    or [`h264parse`](https://gstreamer.freedesktop.org/documentation/videoparsersbad/h264parse.html?gi-language=c) and `avdec_h264`
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    ///
+    /// When capture caps are requested, the device is probed right away so that unsupported
+    /// combinations are reported before the pipeline is built.
+    fn webcamsource(
+        names: &mut ElementRegistry,
+        opts: WebcamOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        // v4l2src [device=...] [! caps] [! decoder] ! videoconvert !
+        let src = names
+            .make_with_role("v4l2src", "source")
+            .property_from_str_if_some("device", opts.device.as_deref())
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        let mut steps = vec![src.clone()];
+
+        let pixel_format = opts.pixel_format.unwrap_or(PixelFormat::Raw);
+        if opts.width_height.is_some() || opts.framerate.is_some() || opts.pixel_format.is_some() {
+            let media_type = match pixel_format {
+                PixelFormat::Raw => "video/x-raw",
+                PixelFormat::Mjpeg => "image/jpeg",
+                PixelFormat::H264 => "video/x-h264",
+            };
+            let caps = gstreamer::Caps::builder(media_type)
+                .field_if_some("width", opts.width_height.map(|(w, _)| w))
+                .field_if_some("height", opts.width_height.map(|(_, h)| h))
+                .field_if_some(
+                    "framerate",
+                    opts.framerate.map(|fps| gstreamer::Fraction::new(fps, 1)),
+                )
+                .build();
+            Self::check_device_caps(&src, &caps)?;
+
+            steps.push(
+                names
+                    .make_with_role("capsfilter", "capture-caps")
+                    .property("caps", caps)
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+        }
+
+        match pixel_format {
+            PixelFormat::Raw => (),
+            PixelFormat::Mjpeg => steps.push(
+                names
+                    .make_with_role("jpegdec", "decoder")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ),
+            PixelFormat::H264 => steps.extend([
+                names
+                    .make("h264parse")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                names
+                    .make_with_role("avdec_h264", "decoder")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ]),
+        }
+
+        steps.push(
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        );
+        Ok(steps)
+    }
+
+    /// Open the capture device behind `src` and check that it can deliver `requested`
+    fn check_device_caps(src: &Element, requested: &gstreamer::Caps) -> Result<(), VideoError> {
+        let device: String = src.property("device");
+        src.set_state(gstreamer::State::Ready)
+            .map_err(|_| VideoError::Gstreamer(Error::DeviceUnavailable(device.clone())))?;
+        let available = src.static_pad("src").map(|pad| pad.query_caps(None));
+        src.set_state(gstreamer::State::Null)
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+
+        match available {
+            Some(caps) if !caps.can_intersect(requested) => Err(VideoError::Gstreamer(
+                Error::DeviceCaps(device, requested.to_string(), caps.to_string()),
+            )),
+            _ => {
+                debug!("Capture device {} can provide {}", device, requested);
+                Ok(())
+            }
+        }
+    }
+
+    /// Create the first steps of the pipeline for a synthetic input:
+    /// 1. [`videotestsrc`](https://gstreamer.freedesktop.org/documentation/videotestsrc/index.html?gi-language=c) producing the requested pattern,
+    ///    and stopping after the requested number of frames, if any
+    /// 1. [`capsfilter`](https://gstreamer.freedesktop.org/documentation/coreelements/capsfilter.html?gi-language=c#capsfilter-page) with the requested resolution and framerate, if any
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    fn testsource(
+        names: &mut ElementRegistry,
+        opts: TestPatternOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        // videotestsrc pattern=... [num-buffers=...] [! caps] ! videoconvert !
+        let mut steps = vec![names
+            .make_with_role("videotestsrc", "source")
+            .property_from_str("pattern", &opts.pattern)
+            .property_if_some("num-buffers", opts.num_frames)
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?];
+
+        if opts.width_height.is_some() || opts.framerate.is_some() {
+            steps.push(
+                names
+                    .make_with_role("capsfilter", "source-caps")
+                    .property(
+                        "caps",
+                        gstreamer::Caps::builder("video/x-raw")
+                            .field_if_some("width", opts.width_height.map(|(w, _)| w))
+                            .field_if_some("height", opts.width_height.map(|(_, h)| h))
+                            .field_if_some(
+                                "framerate",
+                                opts.framerate.map(|fps| gstreamer::Fraction::new(fps, 1)),
+                            )
+                            .build(),
+                    )
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+        }
+
+        steps.push(
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        );
+        Ok(steps)
+    }
+
+    /// Create the first steps of the pipeline for a stream piped to stdin:
+    /// 1. [`fdsrc`](https://gstreamer.freedesktop.org/documentation/coreelements/fdsrc.html?gi-language=c) reading file descriptor 0
+    /// 1. [`decodebin`](https://gstreamer.freedesktop.org/documentation/playback/decodebin.html?gi-language=c),
+    ///    which finds the container (it has to be streamable, e.g. MPEG-TS or Matroska) and picks the demuxer and decoder
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    fn stdinsource(names: &mut ElementRegistry) -> Result<Vec<Element>, VideoError> {
+        // fdsrc fd=0 ! decodebin ! videoconvert !
         Ok(vec![
-            ElementFactory::make("v4l2src")
+            names
+                .make_with_role("fdsrc", "source")
+                .property("fd", 0i32)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("decodebin", "decoder")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("videoconvert")
-                .name("videoconvert0")
+            names
+                .make("videoconvert")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
         ])
     }
 
-    fn source(input: VideoInput) -> Result<Vec<Element>, VideoError> {
+    /// Create the first steps of the pipeline for a headerless raw video file:
+    /// 1. [`filesrc`](https://gstreamer.freedesktop.org/documentation/coreelements/filesrc.html?gi-language=c)
+    /// 1. [`rawvideoparse`](https://gstreamer.freedesktop.org/documentation/rawparse/rawvideoparse.html?gi-language=c)
+    ///    cutting the bytes into frames of the given format and resolution, timestamped at the given framerate;
+    ///    the rows are packed, as in codec test vectors, rather than padded to 4 bytes
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    fn rawsource(
+        names: &mut ElementRegistry,
+        fname: String,
+        opts: RawOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        // filesrc location=... ! rawvideoparse format=... width=... height=... framerate=...
+        //     plane-strides=<...> plane-offsets=<...> frame-size=... ! videoconvert !
+        let (width, height) = opts.width_height;
+        let planes = opts.format.packed_planes(width, height);
+        let strides: Vec<i32> = planes.iter().map(|(stride, _)| *stride).collect();
+        let offsets: Vec<i32> = planes
+            .iter()
+            .scan(0, |offset, (stride, rows)| {
+                let plane = *offset;
+                *offset += stride * rows;
+                Some(plane)
+            })
+            .collect();
+        let frame_size: i32 = planes.iter().map(|(stride, rows)| stride * rows).sum();
+        Ok(vec![
+            names
+                .make_with_role("filesrc", "source")
+                .property("location", fname.as_str())
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("rawvideoparse")
+                .property_from_str("format", &opts.format.to_string())
+                .property("width", width)
+                .property("height", height)
+                .property("framerate", gstreamer::Fraction::new(opts.framerate, 1))
+                .property("plane-strides", gstreamer::Array::new(strides))
+                .property("plane-offsets", gstreamer::Array::new(offsets))
+                .property("frame-size", frame_size as u32)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// Create the first steps of the pipeline for numbered image files:
+    /// 1. [`multifilesrc`](https://gstreamer.freedesktop.org/documentation/multifile/multifilesrc.html?gi-language=c)
+    ///    reading one file per frame, from the first number on, until a file is missing; the
+    ///    caps give the frames their timestamps
+    /// 1. [`pngdec`](https://gstreamer.freedesktop.org/documentation/png/pngdec.html?gi-language=c)
+    ///    or [`jpegdec`](https://gstreamer.freedesktop.org/documentation/jpeg/jpegdec.html?gi-language=c)
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    fn imagesource(
+        names: &mut ElementRegistry,
+        pattern: String,
+        opts: ImageSequenceOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        // multifilesrc location=... index=... caps=image/png,framerate=... ! pngdec ! videoconvert !
+        let (media_type, decoder) = match ImageFormat::from_path(Path::new(&pattern)) {
+            Some(ImageFormat::Jpeg) => ("image/jpeg", "jpegdec"),
+            _ => ("image/png", "pngdec"),
+        };
+        Ok(vec![
+            names
+                .make_with_role("multifilesrc", "source")
+                .property("location", pattern.as_str())
+                .property("index", opts.start_index as i32)
+                .property(
+                    "caps",
+                    gstreamer::Caps::builder(media_type)
+                        .field("framerate", gstreamer::Fraction::new(opts.framerate, 1))
+                        .build(),
+                )
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role(decoder, "decoder")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// Create the first steps of the pipeline for a network stream.
+    /// RTP streams (`rtp://host:port`) are expected to carry `h264` with payload type 96:
+    /// 1. [`udpsrc`](https://gstreamer.freedesktop.org/documentation/udp/udpsrc.html?gi-language=c) listening on the port
+    /// 1. [`rtpjitterbuffer`](https://gstreamer.freedesktop.org/documentation/rtpmanager/rtpjitterbuffer.html?gi-language=c) with the requested latency
+    /// 1. [`rtph264depay`](https://gstreamer.freedesktop.org/documentation/rtp/rtph264depay.html?gi-language=c), `h264parse` and `avdec_h264`
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    ///
+    /// Everything else (http, rtsp, srt, udp) goes through:
+    /// 1. [`uridecodebin`](https://gstreamer.freedesktop.org/documentation/playback/uridecodebin.html?gi-language=c),
+    ///    which picks the source, demuxer and decoder; the requested latency is applied to sources that support it
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    fn urisource(
+        names: &mut ElementRegistry,
+        uri: String,
+        opts: NetworkOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        let mut steps = vec![];
+        if let Some(addr) = uri.strip_prefix("rtp://") {
+            // udpsrc port=... caps=application/x-rtp ! rtpjitterbuffer ! rtph264depay ! h264parse ! avdec_h264 !
+            let (host, port) = addr.rsplit_once(':').unwrap_or(("", addr));
+            steps.push(
+                names
+                    .make_with_role("udpsrc", "source")
+                    .property_from_str("port", port)
+                    .property_if("address", host, !host.is_empty())
+                    .property(
+                        "caps",
+                        gstreamer::Caps::builder("application/x-rtp")
+                            .field("media", "video")
+                            .field("clock-rate", 90000)
+                            .field("encoding-name", "H264")
+                            .field("payload", 96)
+                            .build(),
+                    )
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+            steps.push(
+                names
+                    .make_with_role("rtpjitterbuffer", "jitterbuffer")
+                    .property_if_some("latency", opts.latency_ms)
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+            for factory in ["rtph264depay", "h264parse"] {
+                steps.push(
+                    names
+                        .make(factory)
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+            }
+            steps.push(
+                names
+                    .make_with_role("avdec_h264", "decoder")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+        } else {
+            // uridecodebin uri=... !
+            let decodebin = names
+                .make_with_role("uridecodebin", "source")
+                .property("uri", uri.as_str())
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            if let Some(latency) = opts.latency_ms {
+                decodebin.connect("source-setup", false, move |args| {
+                    if let Ok(src) = args[1].get::<Element>() {
+                        if src.has_property("latency", None) {
+                            debug!("Setting latency of {} to {}ms", src.name(), latency);
+                            src.set_property_from_str("latency", &latency.to_string());
+                        }
+                    }
+                    None
+                });
+            }
+            steps.push(decodebin);
+        }
+
+        steps.push(
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        );
+        Ok(steps)
+    }
+
+    /// Create the first steps of the pipeline for several files played one after another:
+    /// 1. for each file, [`filesrc`](https://gstreamer.freedesktop.org/documentation/coreelements/filesrc.html?gi-language=c),
+    ///    [`decodebin`](https://gstreamer.freedesktop.org/documentation/playback/decodebin.html?gi-language=c),
+    ///    which picks the demuxer and decoder, and a video converter; these chains go to `source_branches`
+    /// 1. [`concat`](https://gstreamer.freedesktop.org/documentation/coreelements/concat.html?gi-language=c),
+    ///    which plays them in order, the timestamps of each file running on from the end of the previous one
+    /// 1. [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    ///
+    /// The files may differ in resolution and framerate: the resize and framerate steps of
+    /// [`build`](crate::Decoder::build) convert them to the same ones.
+    fn concatsource(
+        names: &mut ElementRegistry,
+        fnames: Vec<String>,
+        source_branches: &mut Vec<SourceBranch>,
+    ) -> Result<Vec<Element>, VideoError> {
+        // filesrc location=... ! decodebin ! videoconvert ! concat-0.sink_0  [...]  concat name=concat-0 ! videoconvert !
+        let concat = names
+            .make_with_role("concat", "concat")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        for fname in fnames {
+            let steps = vec![
+                names
+                    .make_with_role("filesrc", "source")
+                    .property("location", fname.as_str())
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                names
+                    .make_with_role("decodebin", "decoder")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                names
+                    .make("videoconvert")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ];
+            source_branches.push(SourceBranch {
+                steps,
+                pad: Self::request_sink_pad(&concat)?,
+            });
+        }
+        Ok(vec![
+            concat,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// A new sink pad of `elem`, which sequences or mixes several inputs, for the next one of them
+    fn request_sink_pad(elem: &Element) -> Result<gstreamer::Pad, VideoError> {
+        elem.request_pad_simple("sink_%u")
+            .ok_or_else(|| VideoError::Gstreamer(Error::RequestPad(elem.name().to_string())))
+    }
+
+    /// Resolution, framerate and duration of the video of file `fname`, found with the
+    /// [`Discoverer`](gstreamer_pbutils::Discoverer)
+    fn probe_clip(fname: &str) -> Result<Clip, VideoError> {
+        let path = Path::new(fname).canonicalize().map_err(VideoError::Io)?;
+        let uri = glib::filename_to_uri(&path, None)
+            .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+        let discoverer = gstreamer_pbutils::Discoverer::new(ClockTime::from_seconds(10))
+            .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+        let info = discoverer
+            .discover_uri(&uri)
+            .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+        let video = info
+            .video_streams()
+            .into_iter()
+            .next()
+            .ok_or_else(|| VideoError::Gstreamer(Error::NoVideo(fname.to_string())))?;
+        let framerate = match video.framerate() {
+            fps if fps.numer() > 0 => fps,
+            _ => gstreamer::Fraction::new(25, 1),
+        };
+        debug!(
+            "{}: {}x{} at {} fps, {}",
+            fname,
+            video.width(),
+            video.height(),
+            framerate,
+            info.duration().display()
+        );
+        Ok(Clip {
+            width_height: (video.width() as i32, video.height() as i32),
+            framerate,
+            duration: info.duration(),
+        })
+    }
+
+    fn source(
+        names: &mut ElementRegistry,
+        input: VideoInput,
+        source_branches: &mut Vec<SourceBranch>,
+    ) -> Result<Vec<Element>, VideoError> {
         match input {
-            VideoInput::File(fname) => Self::filesource(fname),
-            VideoInput::Webcam => Self::webcamsource(),
+            VideoInput::File(fname) => Self::filesource(names, fname),
+            VideoInput::Webcam(opts) => Self::webcamsource(names, opts),
+            VideoInput::TestPattern(opts) => Self::testsource(names, opts),
+            VideoInput::Uri(uri, opts) => Self::urisource(names, uri, opts),
+            VideoInput::ImageSequence(pattern, opts) => Self::imagesource(names, pattern, opts),
+            VideoInput::Raw(fname, opts) => Self::rawsource(names, fname, opts),
+            VideoInput::Stdin => Self::stdinsource(names),
+            VideoInput::Concat(fnames, _) => Self::concatsource(names, fnames, source_branches),
+            VideoInput::PictureInPicture(main, opts) => {
+                Self::pipsource(names, *main, opts, source_branches)
+            }
+            VideoInput::Mosaic(tiles, opts) => {
+                Self::mosaicsource(names, tiles, opts, source_branches)
+            }
         }
     }
 
     /// Create steps for changing the width and height of the video:
     /// 1. [`videoscale`](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoscale.html?gi-language=c#videoscale-page) for resizing the video frames
     /// 1. [`capsfilter`](https://gstreamer.freedesktop.org/documentation/coreelements/capsfilter.html?gi-language=c#capsfilter-page) for specifying the desired width and height
-    fn change_res(opt_w_h: Option<(i32, i32)>) -> Result<Vec<Element>, VideoError> {
+    fn change_res(
+        names: &mut ElementRegistry,
+        opt_w_h: Option<(i32, i32)>,
+    ) -> Result<Vec<Element>, VideoError> {
         if let Some((w, h)) = opt_w_h {
             return Ok(vec![
-                ElementFactory::make("videoscale")
-                    .name("videoscale0")
+                names
+                    .make_with_role("videoscale", "scaler")
                     .build()
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-                ElementFactory::make("capsfilter")
-                    .name("capsfilter0")
+                names
+                    .make_with_role("capsfilter", "resolution")
                     .property(
                         "caps",
                         gstreamer::Caps::builder("video/x-raw")
@@ -115,12 +777,43 @@ impl GstreamerDecoder {
         Ok(vec![])
     }
 
+    /// Create steps for changing the framerate of the video:
+    /// 1. [`videorate`](https://gstreamer.freedesktop.org/documentation/videorate/index.html?gi-language=c) for dropping or duplicating frames
+    /// 1. [`capsfilter`](https://gstreamer.freedesktop.org/documentation/coreelements/capsfilter.html?gi-language=c#capsfilter-page) for specifying the desired framerate
+    fn change_rate(
+        names: &mut ElementRegistry,
+        framerate: Option<gstreamer::Fraction>,
+    ) -> Result<Vec<Element>, VideoError> {
+        if let Some(framerate) = framerate {
+            return Ok(vec![
+                names
+                    .make_with_role("videorate", "rate")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                names
+                    .make_with_role("capsfilter", "framerate")
+                    .property(
+                        "caps",
+                        gstreamer::Caps::builder("video/x-raw")
+                            .field("framerate", framerate)
+                            .build(),
+                    )
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ]);
+        }
+        Ok(vec![])
+    }
+
     /// Create steps for changing the color of the video (note that the filter used here, "xray",
     /// adds a blue hue after inverting - `gstreamer` doesn't have a "just invert" filter):
     /// 1. [`coloreffects`](https://gstreamer.freedesktop.org/documentation/coloreffects/coloreffects.html?gi-language=c) for applying the `xray` effect
     /// 1. [video converter](https:/)/gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page) to automatically convert the video stream into a format
     ///    compatible with whatever comes next in the pipeline
-    fn apply_color_effect(invert: bool) -> Result<Vec<Element>, VideoError> {
+    fn apply_color_effect(
+        names: &mut ElementRegistry,
+        invert: bool,
+    ) -> Result<Vec<Element>, VideoError> {
         if invert {
             // https://gstreamer.freedesktop.org/documentation/coloreffects/coloreffects.html?gi-language=c
             // Color-effects-preset
@@ -133,12 +826,13 @@ impl GstreamerDecoder {
             // xpro (4) – Cross processing toning
             // yellowblue (5) – Yellow foreground Blue background color filter
             Ok(vec![
-                ElementFactory::make("coloreffects")
+                names
+                    .make_with_role("coloreffects", "color")
                     .property_from_str("preset", "xray")
                     .build()
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-                ElementFactory::make("videoconvert")
-                    .name("videoconvert1")
+                names
+                    .make("videoconvert")
                     .build()
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
             ])
@@ -149,7 +843,7 @@ impl GstreamerDecoder {
 
     /// Create steps for flipping the video horizontally:
     /// 1. [`videoflip`](https://gstreamer.freedesktop.org/documentation/videofilter/videoflip.html?gi-language=c)
-    fn flip(flipflag: bool) -> Result<Vec<Element>, VideoError> {
+    fn flip(names: &mut ElementRegistry, flipflag: bool) -> Result<Vec<Element>, VideoError> {
         if flipflag {
             // https://gstreamer.freedesktop.org/documentation/videofilter/videoflip.html?gi-language=c
             // method (deprecated, use video-direction instead)
@@ -167,8 +861,8 @@ impl GstreamerDecoder {
             // upper-right-diagonal (7) – Flip across upper right/lower left diagonal
             // automatic (8) – Select flip method based on image-orientation tag
 
-            Ok(vec![ElementFactory::make("videoflip")
-                .name("videoflip0")
+            Ok(vec![names
+                .make_with_role("videoflip", "flip")
                 .property_from_str("video-direction", "4")
                 .build()
                 .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?])
@@ -177,44 +871,203 @@ impl GstreamerDecoder {
         }
     }
 
-    fn encode(format: VideoFormat) -> Result<Vec<Element>, VideoError> {
+    /// Create steps for the custom filters given as `gst-launch-1.0` fragments, in order.
+    /// Each fragment is parsed into a bin whose unlinked pads are ghosted, followed by a
+    /// [video converter](https://gstreamer.freedesktop.org/documentation/videoconvertscale/videoconvert.html?gi-language=c#videoconvert-page)
+    /// so that the fragment doesn't need to care about the formats around it.
+    /// The bin names and their descriptions are recorded in `fragments`.
+    fn filter_bins(
+        names: &mut ElementRegistry,
+        descriptions: &[String],
+        fragments: &mut HashMap<String, String>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let mut steps = vec![];
+        for desc in descriptions {
+            let name = names.name_for_role("filterbin", "filter");
+            let bin = gstreamer::parse::bin_from_description_with_name(desc, true, &name)
+                .map_err(|e| VideoError::Gstreamer(Error::FilterBinParse(desc.clone(), e)))?;
+            if bin.static_pad("sink").is_none() || bin.static_pad("src").is_none() {
+                return Err(VideoError::Gstreamer(Error::FilterBinPads(desc.clone())));
+            }
+            fragments.insert(name, desc.clone());
+            steps.push(bin.upcast());
+            steps.push(
+                names
+                    .make("videoconvert")
+                    .build()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            );
+        }
+        Ok(steps)
+    }
+
+    fn encode(
+        names: &mut ElementRegistry,
+        format: VideoFormat,
+    ) -> Result<Vec<Element>, VideoError> {
         //   x264enc tune=zerolatency ! queue ! avdec_h264 ! videoconvert !
         match format {
             VideoFormat::H264 => Ok(vec![
-                ElementFactory::make("x264enc")
-                    .name("x264enc0")
+                names
+                    .make_with_role("x264enc", "encoder")
                     .property_from_str("tune", "zerolatency")
                     .build()
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-                ElementFactory::make("queue")
-                    .name("queue0")
+                names
+                    .make("queue")
                     .build()
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
             ]),
         }
     }
 
-    fn screenout() -> Result<Vec<Element>, VideoError> {
-        Ok(vec![
-            ElementFactory::make("avdec_h264")
-                .name("avdec_h2641")
-                .build()
-                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("videoconvert")
-                .name("videoconvert2") // todo: keep a map
-                .build()
-                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-            ElementFactory::make("xvimagesink")
-                .name("xvimagesink0")
-                .build()
-                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
-        ])
+    /// Link consecutive `steps` to each other, waiting for the src pads of the elements that
+    /// create them dynamically
+    fn link_chain(steps: &[Element]) -> Result<(), VideoError> {
+        for i in 0..steps.len().saturating_sub(1) {
+            debug!("Linking {} with {}", steps[i].name(), steps[i + 1].name());
+
+            if Self::has_dynamic_src_pads(&steps[i]) {
+                // Special handling for demux!!
+                // Why?
+                // Because as the name suggests it *demultiplexes* src into multiple streams,
+                // and the next element can't know what to link to unless explicitly shown.
+                //
+                // inspo:
+                // https://stackoverflow.com/a/65591800
+                // https://gitlab.freedesktop.org/gstreamer/gstreamer-rs/-/blob/0b1be1178918166a2e519d82f2935d68034ad046/examples/src/bin/transmux.rs
+                let next_elem = steps[i + 1].clone();
+
+                steps[i].connect_pad_added(move |_demux, src_pad| {
+                    // let self_clone = Rc::clone(&self_clone);
+                    let next_elem = next_elem.clone();
+                    GstreamerDecoder::handle_demux_pad_added(src_pad, &next_elem);
+                });
+
+                steps[i]
+                    .sync_state_with_parent()
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            } else {
+                steps[i].link(&steps[i + 1]).map_err(|e| {
+                    VideoError::Gstreamer(Error::Link(
+                        steps[i].name().to_string(),
+                        steps[i + 1].name().to_string(),
+                        e,
+                    ))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Translate an element message into an [`Event`], if it is one callers care about
+    fn event_from(s: &gstreamer::StructureRef) -> Option<Event> {
+        match s.name().as_str() {
+            // Posted by splitmuxsink once a file is finalized, or by a ring buffer once an
+            // event recording is
+            "splitmuxsink-fragment-closed" | ringbuffer::FILE_CLOSED_MESSAGE => {
+                Some(Event::FileClosed {
+                    path: s.get::<String>("location").ok()?.into(),
+                    running_time: Duration::from_nanos(s.get::<u64>("running-time").ok()?),
+                })
+            }
+            // Posted by a frame extractor once an image is written
+            frames::FRAME_EXTRACTED_MESSAGE => Some(Event::FrameExtracted {
+                path: s.get::<String>("location").ok()?.into(),
+                timestamp: Duration::from_nanos(s.get::<u64>("timestamp").ok()?),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The trigger of the event recording outputs, to start recordings while the pipeline runs.
+    /// `None` if the built pipeline has no event recording output.
+    pub fn trigger(&self) -> Option<Trigger> {
+        if self.recorders.buffers.is_empty() {
+            return None;
+        }
+        Some(self.recorders.trigger.clone())
+    }
+
+    /// The interrupt of the pipeline, to end it cleanly while it runs, e.g. on Ctrl-C.
+    /// `None` when serving RTSP, where the media pipelines belong to the server.
+    pub fn interrupt(&self) -> Option<Interrupt> {
+        match self.outputs.first() {
+            Some(Output::Rtsp { .. }) => None,
+            _ => Some(Interrupt(self.pipeline.clone())),
+        }
+    }
+
+    /// Restrict playback to `start`..`stop` with a seek in the paused pipeline; the stream ends
+    /// at `stop`. Inputs that can't seek (cameras, live streams) are played whole
+    fn seek_range(&self, start: ClockTime, stop: Option<ClockTime>) -> Result<(), VideoError> {
+        self.pipeline
+            .set_state(gstreamer::State::Paused)
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+        let (result, _, _) = self.pipeline.state(ClockTime::NONE);
+        result.map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+
+        let stop_type = match stop {
+            Some(_) => gstreamer::SeekType::Set,
+            None => gstreamer::SeekType::None,
+        };
+        match self.pipeline.seek(
+            1.0,
+            gstreamer::SeekFlags::FLUSH | gstreamer::SeekFlags::ACCURATE,
+            gstreamer::SeekType::Set,
+            Some(start),
+            stop_type,
+            stop,
+        ) {
+            Ok(()) => info!(
+                "Playing from {} to {}",
+                start,
+                stop.map(|t| t.to_string())
+                    .unwrap_or_else(|| "the end".to_string())
+            ),
+            Err(e) => warn!("Can't seek the input, playing all of it: {}", e),
+        }
+        Ok(())
+    }
+
+    /// Whether `elem` creates (some of) its src pads dynamically, like demuxers do.
+    /// Such elements can only be linked to the next one once the pads show up.
+    fn has_dynamic_src_pads(elem: &Element) -> bool {
+        elem.pad_template_list().iter().any(|templ| {
+            templ.direction() == PadDirection::Src && templ.presence() == PadPresence::Sometimes
+        })
+    }
+
+    /// Look up the first element that plays `role` in the pipeline, e.g. `"scaler"`,
+    /// `"encoder"` or `"sink"`, in order to inspect or change its properties at runtime.
+    ///
+    /// Known roles: `source`, `capture-caps`, `source-caps`, `jitterbuffer`, `demuxer`, `decoder`, `concat`,
+    /// `compositor`, `layer`, `label`, `border`, `color`, `scaler`, `resolution`, `rate`, `framerate`, `flip`, `filter`, `tee`, `branch`, `encoder`, `payloader`, `muxer`, `event-buffer`, `output`
+    /// and `sink`. Only the roles used by the built pipeline are present.
+    pub fn element(&self, role: &str) -> Option<Element> {
+        self.elements(role).into_iter().next()
+    }
+
+    /// Look up all the elements that play `role` in the pipeline, in the order they were created
+    pub fn elements(&self, role: &str) -> Vec<Element> {
+        self.names
+            .names(role)
+            .iter()
+            .filter_map(|name| self.bin.by_name(name))
+            .collect()
+    }
+
+    /// The roles played by the elements of the pipeline
+    pub fn roles(&self) -> Vec<String> {
+        self.names.roles().map(str::to_string).collect()
     }
 
     /// Callback for linking the demuxer (dynamically) when the pipeline starts playing.
     /// The [`qtdemux`](https://gstreamer.freedesktop.org/documentation/qtdemux/qtdemux.html?gi-language=c) element can't be
     /// linked to the next element during pipeline creation, hence the need to register a callback
     /// and handle it dynamically at "run"time.
+    /// Only the first video pad is linked, audio and further video streams are ignored.
     fn handle_demux_pad_added(
         demux_src_pad: &gstreamer::Pad,
         next_elem: &gstreamer::Element, // decoder
@@ -224,6 +1077,16 @@ impl GstreamerDecoder {
         let next_elem_sink_pad = next_elem
             .static_pad("sink")
             .expect("Can't create sink pad for demuxer");
+        let is_video = demux_src_pad
+            .current_caps()
+            .unwrap_or_else(|| demux_src_pad.query_caps(None))
+            .structure(0)
+            .is_some_and(|s| s.name().starts_with("video/"));
+        if !is_video || next_elem_sink_pad.is_linked() {
+            debug!("Ignoring pad {}", demux_src_pad.name());
+            return;
+        }
+
         demux_src_pad
             .link(&next_elem_sink_pad)
             .expect("Can't link demuxer to next element");
@@ -231,120 +1094,373 @@ impl GstreamerDecoder {
 }
 
 impl super::Decoder for GstreamerDecoder {
-    /// Create the file source and sink elements that delimit the pipeline
+    /// Create the source elements of the pipeline
     fn new(input: VideoInput) -> Result<Arc<Mutex<Self>>, VideoError> {
         gstreamer::init().map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
 
+        let reconnect = match &input {
+            VideoInput::Uri(_, opts) if opts.reconnect_attempts > 0 => Some(opts.clone()),
+            _ => None,
+        };
+        // Files played in a row are converted to the resolution and framerate of the first one,
+        // unless others are requested
+        let clip_format = match &input {
+            VideoInput::Concat(fnames, opts) => {
+                let clip = Self::probe_clip(&fnames[0])?;
+                Some((
+                    clip.width_height,
+                    opts.framerate
+                        .map(|fps| gstreamer::Fraction::new(fps, 1))
+                        .unwrap_or(clip.framerate),
+                ))
+            }
+            _ => None,
+        };
+        let mut names = ElementRegistry::default();
+        let mut source_branches = vec![];
+        let pipeline = Pipeline::with_name("hc-pipeline");
         Ok(Arc::new(Mutex::new(GstreamerDecoder {
-            srcsteps: Self::source(input)?,
-            sinksteps: Self::screenout()?,
-            pipeline: Pipeline::with_name("hc-pipeline"),
+            input: input.clone(),
+            options: None,
+            srcsteps: Self::source(&mut names, input, &mut source_branches)?,
+            source_branches,
+            clip_format,
+            steps: vec![],
+            branches: vec![],
+            bin: pipeline.clone().upcast(),
+            pipeline,
+            graph: None,
+            fragments: HashMap::new(),
+            names,
+            reconnect,
+            outputs: vec![],
+            event_handlers: vec![],
+            recorders: EventRecorders::default(),
+            range: None,
+            preparations: vec![],
         })))
     }
 
     /// Build the gstreamer pipeline.
     /// When all the supported filters are added, the pipeline looks like this:
     ///
+    /// ```text
+    /// {source} - {coloreffects} - {videoconvert} - {videoscale} - {capsfilter} - {videoflip} - {filter bins} - {output steps}
     /// ```
-    /// {source} - {coloreffects} - {videoconvert} - {videoscale} - {capsfilter} - {videoflip} - {encode} {xvimgsink}
+    ///
+    /// With several outputs, the filtered video goes through a tee instead, and each output gets
+    /// its own branch:
+    ///
+    /// ```text
+    /// ... - {filter bins} - {tee} - {queue} - {output steps}
+    ///                             - {queue} - {output steps}
     /// ```
+    ///
+    /// where the output steps are {videoscale} - {capsfilter} (if the output has its own
+    /// resolution) - {encode} - {sink}.
+    ///
+    /// With several input files, the source is a concat fed by one chain per file, and the
+    /// resize steps are always there, followed by {videorate} - {capsfilter}, so that all the
+    /// files come out at the same resolution and framerate: the requested ones, or the first
+    /// file's.
     fn build(self_rc: Arc<Mutex<Self>>, opts: DecoderOptions) -> Result<(), VideoError> {
         let mut lock = self_rc.lock();
         let decoder = lock.as_deref_mut().map_err(|_| VideoError::PoisonedLock)?;
 
-        let filter_steps = Self::apply_color_effect(opts.invert)
+        let names = &mut decoder.names;
+        let width_height = opts
+            .width_height
+            .or(decoder.clip_format.map(|(width_height, _)| width_height));
+        let framerate = decoder.clip_format.map(|(_, framerate)| framerate);
+        let filter_steps = Self::apply_color_effect(names, opts.invert)
             .and_then(|mut v| {
-                let resize_steps = Self::change_res(opts.width_height)?;
+                let resize_steps = Self::change_res(names, width_height)?;
                 v.extend(resize_steps);
+                let rate_steps = Self::change_rate(names, framerate)?;
+                v.extend(rate_steps);
                 Ok(v)
             })
             .and_then(|mut v| {
-                let flip_steps = Self::flip(opts.flip)?;
+                let flip_steps = Self::flip(names, opts.flip)?;
                 v.extend(flip_steps);
                 Ok(v)
             })
             .and_then(|mut v| {
-                let encode_steps = Self::encode(opts.format)?;
-                v.extend(encode_steps);
+                let bin_steps =
+                    Self::filter_bins(names, &opts.filter_bins, &mut decoder.fragments)?;
+                v.extend(bin_steps);
                 Ok(v)
             })?;
 
         let mut all_steps: Vec<Element> = decoder.srcsteps.clone();
         all_steps.extend(filter_steps);
-        all_steps.extend(decoder.sinksteps.clone());
+
+        // The directories come first, the outputs may write files to them before starting
+        decoder.preparations.extend(
+            opts.outputs
+                .iter()
+                .filter_map(|output| output.target.dir())
+                .chain(opts.dump_graph.as_deref())
+                .map(|dir| Preparation::Dir(dir.to_path_buf())),
+        );
+
+        // A single output continues the chain, several fan out through a tee
+        let mut branches = vec![];
+        if let [output] = opts.outputs.as_slice() {
+            all_steps.extend(Self::output_steps(
+                names,
+                output,
+                opts.format,
+                &mut decoder.fragments,
+                &mut decoder.recorders,
+                &mut decoder.preparations,
+            )?);
+        } else {
+            all_steps.push(Self::fanout(names)?);
+            for output in &opts.outputs {
+                branches.push(Self::branch(
+                    names,
+                    output,
+                    opts.format,
+                    &mut decoder.fragments,
+                    &mut decoder.recorders,
+                    &mut decoder.preparations,
+                )?);
+            }
+        }
 
         decoder
-            .pipeline
+            .bin
             .add_many(all_steps.iter())
             .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        Self::link_chain(&all_steps)?;
 
-        for i in 0..all_steps.len() - 1 {
-            println!(
-                "Linking {} with {}",
-                all_steps[i].name(),
-                all_steps[i + 1].name()
-            );
-
-            if all_steps[i].name() == "demux" {
-                // Special handling for demux!!
-                // Why?
-                // Because as the name suggests it *demultiplexes* src into multiple streams,
-                // and the next element can't know what to link to unless explicitly shown.
-                //
-                // inspo:
-                // https://stackoverflow.com/a/65591800
-                // https://gitlab.freedesktop.org/gstreamer/gstreamer-rs/-/blob/0b1be1178918166a2e519d82f2935d68034ad046/examples/src/bin/transmux.rs
-                let next_elem = all_steps[i + 1].clone();
-
-                all_steps[i].connect_pad_added(move |_demux, src_pad| {
-                    // let self_clone = Rc::clone(&self_clone);
-                    let next_elem = next_elem.clone();
-                    GstreamerDecoder::handle_demux_pad_added(&src_pad, &next_elem);
-                });
+        // Each input of a concat or compositor goes to the sink pad requested for it
+        for branch in &decoder.source_branches {
+            decoder
+                .bin
+                .add_many(branch.steps.iter())
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            Self::link_chain(&branch.steps)?;
+            if let (Some(last), Some(first)) = (branch.steps.last(), all_steps.first()) {
+                last.link_pads(None, first, Some(branch.pad.name().as_str()))
+                    .map_err(|e| {
+                        VideoError::Gstreamer(Error::Link(
+                            last.name().to_string(),
+                            format!("{}.{}", first.name(), branch.pad.name()),
+                            e,
+                        ))
+                    })?;
+            }
+        }
 
-                all_steps[i]
-                    .sync_state_with_parent()
-                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
-            } else {
-                all_steps[i]
-                    .link(&all_steps[i + 1])
+        if let Some(tee) = all_steps.last().filter(|_| !branches.is_empty()) {
+            for branch in &branches {
+                decoder
+                    .bin
+                    .add_many(branch.iter())
                     .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+                tee.link(&branch[0]).map_err(|e| {
+                    VideoError::Gstreamer(Error::Link(
+                        tee.name().to_string(),
+                        branch[0].name().to_string(),
+                        e,
+                    ))
+                })?;
+                Self::link_chain(branch)?;
             }
         }
 
+        decoder.steps = all_steps;
+        decoder.branches = branches;
+        if let Some(Output::Rtsp { .. }) = opts.outputs.first().map(|out| &out.target) {
+            decoder.options = Some(opts.clone());
+        }
+        decoder.outputs = opts.outputs.into_iter().map(|out| out.target).collect();
+        if opts.start_secs.is_some() || opts.duration_secs.is_some() {
+            let start = ClockTime::from_seconds_f64(opts.start_secs.unwrap_or(0.0));
+            let stop = opts
+                .duration_secs
+                .map(|secs| start + ClockTime::from_seconds_f64(secs));
+            decoder.range = Some((start, stop));
+        }
+        decoder.graph = opts
+            .dump_graph
+            .map(|dir| GraphDumper::new(dir, opts.graph_format));
         Ok(())
     }
 
-    /// Play the pipeline (run the video through the filters and play it on the screen)
+    /// Describe the built pipeline as the equivalent `gst-launch-1.0` command line,
+    /// including the element properties and caps set by [`build`](Self::build)
+    fn describe(&self) -> String {
+        launch::launch_line(
+            &self.source_branches,
+            &self.steps,
+            &self.branches,
+            &self.fragments,
+        )
+    }
+
+    /// Play the pipeline (run the video through the filters and play it on the screen),
+    /// or serve it over RTSP
     fn run(&mut self) -> Result<(), VideoError> {
-        self.pipeline
-            .set_state(gstreamer::State::Playing)
-            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+        for preparation in &self.preparations {
+            preparation.apply()?;
+        }
+        if let Some(Output::Rtsp { port, mount }) = self.outputs.first() {
+            return self.serve_rtsp(*port, mount);
+        }
+        info!("Starting pipeline {}", self.pipeline.name());
+        if let Some((start, stop)) = self.range {
+            self.seek_range(start, stop)?;
+        }
+        if let Err(e) = self.pipeline.set_state(gstreamer::State::Playing) {
+            if let Some(graph) = self.graph.as_mut() {
+                graph.dump(&self.pipeline, "error");
+            }
+            return Err(VideoError::Gstreamer(Error::PipelineStateChange(e)));
+        }
 
         let bus = self
             .pipeline
             .bus()
             .ok_or(VideoError::Gstreamer(Error::Bus))?;
 
+        let mut reconnects = 0;
+        let mut failure = None;
         for msg in bus.iter_timed(gstreamer::ClockTime::NONE) {
             use gstreamer::MessageView;
 
             match msg.view() {
-                MessageView::Eos(..) => break,
-                MessageView::Error(_) => {
+                MessageView::Eos(..) => {
+                    info!("End of stream");
+                    break;
+                }
+                MessageView::Error(err) => {
+                    error!(
+                        "Error from {}: {} ({})",
+                        err.src()
+                            .map(|s| s.path_string().to_string())
+                            .unwrap_or_default(),
+                        err.error(),
+                        err.debug().unwrap_or_default()
+                    );
+                    if let Some(graph) = self.graph.as_mut() {
+                        graph.dump(&self.pipeline, "error");
+                    }
                     self.pipeline
                         .set_state(gstreamer::State::Null)
                         .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
-                    // todo log error
-                    break;
+
+                    match &self.reconnect {
+                        Some(policy) if reconnects < policy.reconnect_attempts => {
+                            reconnects += 1;
+                            warn!(
+                                "Reconnecting in {}s (attempt {}/{})",
+                                policy.reconnect_delay_secs, reconnects, policy.reconnect_attempts
+                            );
+                            thread::sleep(Duration::from_secs(policy.reconnect_delay_secs));
+                            self.pipeline
+                                .set_state(gstreamer::State::Playing)
+                                .map_err(|e| {
+                                    VideoError::Gstreamer(Error::PipelineStateChange(e))
+                                })?;
+                        }
+                        // Out of attempts, or none allowed: fail, so that a supervisor restarts us
+                        _ => {
+                            failure = Some(match reconnects {
+                                0 => err.error().to_string(),
+                                _ => format!(
+                                    "{}, after {} reconnection attempt(s)",
+                                    err.error(),
+                                    reconnects
+                                ),
+                            });
+                            break;
+                        }
+                    }
+                }
+                MessageView::Warning(w) => {
+                    warn!(
+                        "Warning from {}: {} ({})",
+                        w.src()
+                            .map(|s| s.path_string().to_string())
+                            .unwrap_or_default(),
+                        w.error(),
+                        w.debug().unwrap_or_default()
+                    );
+                }
+                MessageView::Element(elem) => {
+                    if let Some(event) = elem.structure().and_then(Self::event_from) {
+                        debug!("{}", event);
+                        for handler in &self.event_handlers {
+                            handler(&event);
+                        }
+                    }
+                }
+                MessageView::StateChanged(sc)
+                    if msg.src() == Some(self.pipeline.upcast_ref::<gstreamer::Object>()) =>
+                {
+                    debug!(
+                        "Pipeline state changed from {:?} to {:?}",
+                        sc.old(),
+                        sc.current()
+                    );
+                    if let Some(graph) = self.graph.as_mut() {
+                        let label = format!("{:?}_{:?}", sc.old(), sc.current()).to_lowercase();
+                        graph.dump(&self.pipeline, &label);
+                    }
+                    // Playing again after a reconnection: the attempts count from zero at the
+                    // next error, so only consecutive failures add up to --reconnect
+                    if sc.current() == gstreamer::State::Playing && reconnects > 0 {
+                        info!("Reconnected after {} attempt(s)", reconnects);
+                        reconnects = 0;
+                    }
                 }
                 _ => (),
             }
         }
 
+        // Let the event recordings finish, and announce them before the bus is flushed
+        for ring in &self.recorders.buffers {
+            RingBuffer::close(ring);
+        }
+        while let Some(msg) = bus.pop_filtered(&[gstreamer::MessageType::Element]) {
+            if let Some(event) = msg.structure().and_then(Self::event_from) {
+                for handler in &self.event_handlers {
+                    handler(&event);
+                }
+            }
+        }
+
         self.pipeline
             .set_state(gstreamer::State::Null)
-            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))
-            .map(|_| ())
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+        match failure {
+            Some(e) => Err(VideoError::Gstreamer(Error::Pipeline(e))),
+            None => Ok(()),
+        }
+    }
+
+    fn on_event<F: Fn(&Event) + Send + 'static>(&mut self, handler: F) {
+        self.event_handlers.push(Box::new(handler));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepared_directories_are_created_and_left_empty() {
+        let root = std::env::temp_dir().join(format!("hc-preparation-{}", process::id()));
+        let dir = root.join("a/b");
+        Preparation::Dir(dir.clone()).apply().unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::write(root.join("file"), b"").unwrap();
+        assert!(Preparation::Dir(root.join("file/sub")).apply().is_err());
+        fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use gstreamer::prelude::Cast;
+use gstreamer::{Element, FlowError, FlowSuccess, Sample};
+use gstreamer_app::{AppSink, AppSinkCallbacks};
+use gstreamer_video::prelude::VideoFrameExt;
+use gstreamer_video::{VideoFrameRef, VideoInfo};
+use log::debug;
+
+use util::RawFormat;
+
+/// Writes the frames from an `appsink` one after the other to a headerless raw file, with the
+/// rows of each plane packed: gstreamer pads them to a multiple of 4 bytes, which reference
+/// outputs don't have.
+///
+/// The file is created with the first frame, once the pipeline runs.
+pub(crate) struct RawFileWriter {
+    path: PathBuf,
+    format: RawFormat,
+    file: Option<BufWriter<File>>,
+    /// The appsink feeding the writer, where write errors are reported
+    sink: Element,
+}
+
+impl RawFileWriter {
+    /// Write the samples of `sink` to `path`, in `format`
+    pub(crate) fn attach(sink: &AppSink, path: &Path, format: RawFormat) {
+        let writer = Arc::new(Mutex::new(RawFileWriter {
+            path: path.to_path_buf(),
+            format,
+            file: None,
+            sink: sink.clone().upcast(),
+        }));
+
+        let on_eos = writer.clone();
+        sink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                    match writer.lock() {
+                        Ok(mut writer) => writer.push(&sample),
+                        Err(_) => Err(FlowError::Error),
+                    }
+                })
+                .eos(move |_sink| {
+                    if let Ok(mut writer) = on_eos.lock() {
+                        // Even without frames, the output exists once the stream ends
+                        writer.finish();
+                    }
+                })
+                .build(),
+        );
+    }
+
+    /// Append the frame in `sample` to the file
+    fn push(&mut self, sample: &Sample) -> Result<FlowSuccess, FlowError> {
+        let (Some(buffer), Some(caps)) = (sample.buffer(), sample.caps()) else {
+            return Ok(FlowSuccess::Ok);
+        };
+        let info = VideoInfo::from_caps(caps).map_err(|_| FlowError::NotNegotiated)?;
+        let frame =
+            VideoFrameRef::from_buffer_ref_readable(buffer, &info).map_err(|_| FlowError::Error)?;
+
+        let planes = self
+            .format
+            .packed_planes(info.width() as i32, info.height() as i32);
+        let mut packed = vec![];
+        for (plane, (row_size, rows)) in planes.into_iter().enumerate() {
+            let data = frame
+                .plane_data(plane as u32)
+                .map_err(|_| FlowError::Error)?;
+            let stride = frame.plane_stride()[plane] as usize;
+            for row in 0..rows as usize {
+                let start = row * stride;
+                let bytes = data
+                    .get(start..start + row_size as usize)
+                    .ok_or(FlowError::Error)?;
+                packed.extend_from_slice(bytes);
+            }
+        }
+
+        let written = self.file().and_then(|file| file.write_all(&packed));
+        if let Err(e) = written {
+            self.write_error(e);
+            return Err(FlowError::Error);
+        }
+        Ok(FlowSuccess::Ok)
+    }
+
+    /// Write what is left in the buffer to the file, created if no frame came
+    fn finish(&mut self) {
+        let flushed = self.file().and_then(|file| file.flush());
+        match flushed {
+            Ok(()) => debug!("{} written", self.path.display()),
+            Err(e) => self.write_error(e),
+        }
+    }
+
+    /// The file, created on first use
+    fn file(&mut self) -> io::Result<&mut BufWriter<File>> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => BufWriter::new(File::create(&self.path)?),
+        };
+        Ok(self.file.insert(file))
+    }
+
+    /// Report a failed write on the appsink
+    fn write_error(&self, e: io::Error) {
+        gstreamer::element_error!(
+            self.sink,
+            gstreamer::ResourceError::Write,
+            ("Can't write {}: {}", self.path.display(), e)
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use gstreamer::element_factory::ElementBuilder;
+use gstreamer::ElementFactory;
+
+/// Hands out unique, readable element names and remembers which elements play which role.
+///
+/// Names are built from the factory name and a per-factory counter (`videoconvert-0`,
+/// `avdec_h264-1`, ...), so adding the same kind of element twice never clashes in the
+/// pipeline. Roles are logical names such as `"scaler"` or `"encoder"`; several elements
+/// can share a role, in which case they are kept in creation order.
+#[derive(Default)]
+pub(crate) struct ElementRegistry {
+    counters: HashMap<String, u32>,
+    roles: HashMap<String, Vec<String>>,
+}
+
+impl ElementRegistry {
+    /// Reserve the next free name for an element created from `factory`
+    pub(crate) fn unique_name(&mut self, factory: &str) -> String {
+        let counter = self.counters.entry(factory.to_string()).or_default();
+        // The separator keeps factory names that end in digits (`avdec_h264`) readable
+        let name = format!("{}-{}", factory, counter);
+        *counter += 1;
+        name
+    }
+
+    /// Reserve a name for an element created from `factory` and record it under `role`
+    pub(crate) fn name_for_role(&mut self, factory: &str, role: &str) -> String {
+        let name = self.unique_name(factory);
+        self.roles
+            .entry(role.to_string())
+            .or_default()
+            .push(name.clone());
+        name
+    }
+
+    /// Start building an element from `factory`, with a unique name
+    pub(crate) fn make<'a>(&mut self, factory: &'a str) -> ElementBuilder<'a> {
+        ElementFactory::make(factory).name(self.unique_name(factory))
+    }
+
+    /// Start building an element from `factory`, with a unique name, recorded under `role`
+    pub(crate) fn make_with_role<'a>(
+        &mut self,
+        factory: &'a str,
+        role: &str,
+    ) -> ElementBuilder<'a> {
+        ElementFactory::make(factory).name(self.name_for_role(factory, role))
+    }
+
+    /// Start building an element from `factory` with a fixed `name`, recorded under `role`,
+    /// for the elements that others look up by name (e.g. the RTSP server's `pay0`)
+    pub(crate) fn make_named<'a>(
+        &mut self,
+        factory: &'a str,
+        name: &str,
+        role: &str,
+    ) -> ElementBuilder<'a> {
+        self.roles
+            .entry(role.to_string())
+            .or_default()
+            .push(name.to_string());
+        ElementFactory::make(factory).name(name)
+    }
+
+    /// Names of the elements recorded under `role`, in creation order
+    pub(crate) fn names(&self, role: &str) -> &[String] {
+        self.roles.get(role).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// All the known roles
+    pub(crate) fn roles(&self) -> impl Iterator<Item = &str> {
+        self.roles.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gstreamer::prelude::GstObjectExt;
+
+    use super::*;
+
+    #[test]
+    fn names_are_unique_per_factory() {
+        let mut names = ElementRegistry::default();
+        assert_eq!(names.unique_name("videoconvert"), "videoconvert-0");
+        assert_eq!(names.unique_name("avdec_h264"), "avdec_h264-0");
+        assert_eq!(names.unique_name("videoconvert"), "videoconvert-1");
+        assert_eq!(names.unique_name("avdec_h264"), "avdec_h264-1");
+    }
+
+    #[test]
+    fn roles_keep_creation_order() {
+        let mut names = ElementRegistry::default();
+        assert_eq!(names.name_for_role("queue", "branch"), "queue-0");
+        names.unique_name("queue");
+        assert_eq!(names.name_for_role("x264enc", "encoder"), "x264enc-0");
+        assert_eq!(names.name_for_role("queue", "branch"), "queue-2");
+        assert_eq!(names.names("branch"), ["queue-0", "queue-2"]);
+        assert_eq!(names.names("encoder"), ["x264enc-0"]);
+        assert!(names.names("muxer").is_empty());
+
+        let mut roles: Vec<_> = names.roles().collect();
+        roles.sort();
+        assert_eq!(roles, ["branch", "encoder"]);
+    }
+
+    #[test]
+    fn fixed_names_are_recorded_under_their_role() {
+        gstreamer::init().unwrap();
+        let mut names = ElementRegistry::default();
+        let pay = names
+            .make_named("identity", "pay0", "payloader")
+            .build()
+            .unwrap();
+        assert_eq!(pay.name(), "pay0");
+        assert_eq!(names.names("payloader"), ["pay0"]);
+        // Fixed names don't use up the factory's counter
+        assert_eq!(names.unique_name("identity"), "identity-0");
+    }
+}
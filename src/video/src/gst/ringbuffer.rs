@@ -0,0 +1,433 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use gstreamer::prelude::{Cast, ElementExt, GstBinExtManual};
+use gstreamer::{
+    BufferFlags, ClockTime, Element, ElementFactory, FormattedSegment, Pipeline, Sample, Structure,
+};
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
+use log::{debug, error, info};
+
+use super::Error;
+use crate::Error as VideoError;
+
+/// Name of the element message posted when an event recording is complete
+pub(crate) const FILE_CLOSED_MESSAGE: &str = "ringbuffer-file-closed";
+
+/// Starts event recordings on all the event recording outputs of a pipeline.
+///
+/// Cheap to clone and usable from any thread, e.g. by a signal handler or a detector running
+/// next to the pipeline.
+#[derive(Clone, Default)]
+pub struct Trigger(Arc<AtomicU64>);
+
+impl Trigger {
+    /// Record the buffered pre-event video and the following seconds to a new file.
+    /// Firing while a recording is running extends it instead
+    pub fn fire(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// How many times the trigger was fired so far
+    fn count(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A file being written by an event recording
+struct Recording {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+    path: PathBuf,
+    /// Timestamp of the first buffer, which becomes 0 in the file
+    base: ClockTime,
+    /// Timestamp after which the recording stops
+    until: ClockTime,
+}
+
+/// Keeps the last seconds of encoded video in memory, from an `appsink`, and writes them to a
+/// new file with what follows when the [`Trigger`] fires.
+///
+/// The buffer is trimmed by whole GOPs, so it always starts on a keyframe and every
+/// recording plays on its own. It holds at least `pre` of video, plus up to one GOP.
+pub(crate) struct RingBuffer {
+    /// File name of the recordings, numbered for each of them
+    path: PathBuf,
+    muxer: &'static str,
+    pre: ClockTime,
+    post: ClockTime,
+    trigger: Trigger,
+    /// Trigger count last acted upon
+    seen: u64,
+    samples: VecDeque<Sample>,
+    /// Segment of the last sample, which turns timestamps into running times
+    segment: Option<FormattedSegment<ClockTime>>,
+    recording: Option<Recording>,
+    /// Number of recordings started so far
+    count: u32,
+    /// The appsink feeding the buffer, where recordings are announced
+    sink: Element,
+    /// Recordings being finalized
+    finishing: Vec<JoinHandle<()>>,
+}
+
+/// Whether `sample` holds a keyframe, which a recording can start with
+fn is_keyframe(sample: &Sample) -> bool {
+    sample
+        .buffer()
+        .is_some_and(|buf| !buf.flags().contains(BufferFlags::DELTA_UNIT))
+}
+
+/// Timestamp of the buffer in `sample`
+fn pts(sample: &Sample) -> Option<ClockTime> {
+    sample.buffer().and_then(|buf| buf.pts())
+}
+
+impl RingBuffer {
+    /// Buffer the samples of `sink`, for recordings to `path` through `muxer`
+    pub(crate) fn attach(
+        sink: &AppSink,
+        path: &Path,
+        muxer: &'static str,
+        pre_secs: u64,
+        post_secs: u64,
+        trigger: &Trigger,
+    ) -> Arc<Mutex<Self>> {
+        let ring = Arc::new(Mutex::new(RingBuffer {
+            path: path.to_path_buf(),
+            muxer,
+            pre: ClockTime::from_seconds(pre_secs),
+            post: ClockTime::from_seconds(post_secs),
+            trigger: trigger.clone(),
+            seen: trigger.count(),
+            samples: VecDeque::new(),
+            segment: None,
+            recording: None,
+            count: 0,
+            sink: sink.clone().upcast(),
+            finishing: vec![],
+        }));
+
+        let on_sample = ring.clone();
+        let on_eos = ring.clone();
+        sink.set_callbacks(
+            AppSinkCallbacks::builder()
+                .new_sample(move |sink| {
+                    let sample = sink.pull_sample().map_err(|_| gstreamer::FlowError::Eos)?;
+                    if let Ok(mut ring) = on_sample.lock() {
+                        ring.push(sample);
+                    }
+                    Ok(gstreamer::FlowSuccess::Ok)
+                })
+                .eos(move |_sink| {
+                    if let Ok(mut ring) = on_eos.lock() {
+                        ring.finish();
+                    }
+                })
+                .build(),
+        );
+        ring
+    }
+
+    /// Buffer a new sample, starting, feeding or stopping the recording as needed
+    fn push(&mut self, sample: Sample) {
+        let Some(now) = pts(&sample) else {
+            return;
+        };
+        if let Some(segment) = sample.segment().and_then(|s| s.downcast_ref::<ClockTime>()) {
+            self.segment = Some(segment.clone());
+        }
+        self.samples.push_back(sample.clone());
+        Self::trim(&mut self.samples, self.pre, now);
+
+        let count = self.trigger.count();
+        let triggered = count != self.seen;
+        self.seen = count;
+
+        match self.recording.as_mut() {
+            Some(recording) => {
+                if triggered {
+                    recording.until = now + self.post;
+                    info!("Event recording {} extended", recording.path.display());
+                }
+                Self::feed(recording, &sample);
+            }
+            None if triggered => {
+                if let Err(e) = self.start(now) {
+                    error!("Can't start event recording: {}", e);
+                }
+            }
+            None => (),
+        }
+
+        if self
+            .recording
+            .as_ref()
+            .is_some_and(|recording| now >= recording.until)
+        {
+            self.finish();
+        }
+    }
+
+    /// Drop the GOPs of `samples` that are entirely older than the `pre`-event window
+    fn trim(samples: &mut VecDeque<Sample>, pre: ClockTime, now: ClockTime) {
+        while samples.front().is_some_and(|s| !is_keyframe(s)) {
+            samples.pop_front();
+        }
+        while let Some(next_gop) = samples.iter().skip(1).position(is_keyframe) {
+            match pts(&samples[next_gop + 1]) {
+                Some(start) if start + pre <= now => {
+                    samples.drain(..=next_gop);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Start recording to a new file, beginning with the buffered samples
+    fn start(&mut self, now: ClockTime) -> Result<(), VideoError> {
+        let Some(first) = self.samples.front() else {
+            return Ok(());
+        };
+        let base = pts(first).unwrap_or(now);
+        let caps = first.caps().map(|caps| caps.to_owned());
+
+        let path = Self::numbered(&self.path, self.count);
+        self.count += 1;
+
+        let mut appsrc = AppSrc::builder().format(gstreamer::Format::Time);
+        if let Some(caps) = caps.as_ref() {
+            appsrc = appsrc.caps(caps);
+        }
+        let appsrc = appsrc.build();
+        let steps = [
+            appsrc.clone().upcast(),
+            ElementFactory::make("h264parse")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ElementFactory::make(self.muxer)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            ElementFactory::make("filesink")
+                .property("location", path.to_string_lossy().to_string())
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ];
+        let pipeline = Pipeline::with_name(&format!("hc-event-{}", self.count - 1));
+        pipeline
+            .add_many(steps.iter())
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        Element::link_many(steps.iter()).map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        pipeline
+            .set_state(gstreamer::State::Playing)
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+
+        info!(
+            "Event recording to {}, starting {:.1}s before the event",
+            path.display(),
+            (now.saturating_sub(base)).seconds_f64()
+        );
+        let recording = Recording {
+            pipeline,
+            appsrc,
+            path,
+            base,
+            until: now + self.post,
+        };
+        for sample in &self.samples {
+            Self::feed(&recording, sample);
+        }
+        self.recording = Some(recording);
+        Ok(())
+    }
+
+    /// Write a sample to the recording, with timestamps starting at 0
+    fn feed(recording: &Recording, sample: &Sample) {
+        let Some(buffer) = sample.buffer() else {
+            return;
+        };
+        let mut buffer = buffer.copy();
+        {
+            let buffer = buffer.make_mut();
+            buffer.set_pts(buffer.pts().map(|pts| pts.saturating_sub(recording.base)));
+            buffer.set_dts(buffer.dts().map(|dts| dts.saturating_sub(recording.base)));
+        }
+        if let Err(e) = recording.appsrc.push_buffer(buffer) {
+            debug!(
+                "Event recording {} refused a buffer: {}",
+                recording.path.display(),
+                e
+            );
+        }
+    }
+
+    /// Stop the current recording, if any, and finalize the file in the background.
+    /// Once complete, the file is announced with an element message from the appsink.
+    fn finish(&mut self) {
+        let Some(recording) = self.recording.take() else {
+            return;
+        };
+        let _ = recording.appsrc.end_of_stream();
+        let sink = self.sink.clone();
+        // The timestamps are converted with the segment, like the running time reported by
+        // splitmuxsink; the default segment leaves them unchanged
+        let running_time = self
+            .segment
+            .as_ref()
+            .and_then(|segment| segment.to_running_time(recording.until))
+            .unwrap_or(recording.until);
+
+        self.finishing.push(thread::spawn(move || {
+            if let Some(bus) = recording.pipeline.bus() {
+                if let Some(msg) = bus.timed_pop_filtered(
+                    ClockTime::NONE,
+                    &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+                ) {
+                    if let gstreamer::MessageView::Error(err) = msg.view() {
+                        error!(
+                            "Event recording {} failed: {}",
+                            recording.path.display(),
+                            err.error()
+                        );
+                    }
+                }
+            }
+            let _ = recording.pipeline.set_state(gstreamer::State::Null);
+            info!("Event recording {} complete", recording.path.display());
+
+            let structure = Structure::builder(FILE_CLOSED_MESSAGE)
+                .field("location", recording.path.to_string_lossy().to_string())
+                .field("running-time", running_time.nseconds())
+                .build();
+            let _ = sink.post_message(
+                gstreamer::message::Element::builder(structure)
+                    .src(&sink)
+                    .build(),
+            );
+        }));
+    }
+
+    /// Stop the current recording, if any, and wait until all the recordings are written
+    pub(crate) fn close(ring: &Mutex<Self>) {
+        let finishing = match ring.lock() {
+            Ok(mut ring) => {
+                ring.finish();
+                std::mem::take(&mut ring.finishing)
+            }
+            Err(_) => return,
+        };
+        for handle in finishing {
+            let _ = handle.join();
+        }
+    }
+
+    /// The path of the `n`th recording: `rec.mp4` becomes `rec_00000.mp4`, `rec_00001.mp4`, ...
+    fn numbered(path: &Path, n: u32) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{}_{:05}.{}", stem, n, ext.to_string_lossy()),
+            None => format!("{}_{:05}", stem, n),
+        };
+        path.with_file_name(name)
+    }
+}
+
+/// The ring buffers of a pipeline and the trigger they share
+#[derive(Default)]
+pub(crate) struct EventRecorders {
+    pub(crate) trigger: Trigger,
+    pub(crate) buffers: Vec<Arc<Mutex<RingBuffer>>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample at `secs`, a keyframe or not
+    fn sample(secs: u64, keyframe: bool) -> Sample {
+        let mut buffer = gstreamer::Buffer::new();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(ClockTime::from_seconds(secs));
+            if !keyframe {
+                buffer.set_flags(BufferFlags::DELTA_UNIT);
+            }
+        }
+        Sample::builder().buffer(&buffer).build()
+    }
+
+    /// Timestamps, in seconds, of the samples left
+    fn secs(samples: &VecDeque<Sample>) -> Vec<u64> {
+        samples
+            .iter()
+            .filter_map(|sample| pts(sample).map(ClockTime::seconds))
+            .collect()
+    }
+
+    /// One sample per second from 0 to `until`, with a keyframe every 3 seconds
+    fn samples(until: u64) -> VecDeque<Sample> {
+        gstreamer::init().unwrap();
+        let keyframes: Vec<u64> = (0..=until).step_by(3).collect();
+        (0..=until)
+            .map(|secs| sample(secs, keyframes.contains(&secs)))
+            .collect()
+    }
+
+    #[test]
+    fn recordings_are_numbered() {
+        assert_eq!(
+            RingBuffer::numbered(Path::new("out/event.mp4"), 0),
+            PathBuf::from("out/event_00000.mp4")
+        );
+        assert_eq!(
+            RingBuffer::numbered(Path::new("event.mkv"), 12),
+            PathBuf::from("event_00012.mkv")
+        );
+        assert_eq!(
+            RingBuffer::numbered(Path::new("out/event"), 3),
+            PathBuf::from("out/event_00003")
+        );
+    }
+
+    #[test]
+    fn trimming_keeps_the_pre_event_window_in_whole_gops() {
+        let mut buffered = samples(10);
+        RingBuffer::trim(
+            &mut buffered,
+            ClockTime::from_seconds(4),
+            ClockTime::from_seconds(10),
+        );
+        // The GOP at 6 s starts the window, the one at 3 s is entirely older
+        assert_eq!(secs(&buffered), vec![6, 7, 8, 9, 10]);
+
+        let mut buffered = samples(10);
+        RingBuffer::trim(
+            &mut buffered,
+            ClockTime::from_seconds(5),
+            ClockTime::from_seconds(10),
+        );
+        // Up to one more GOP: the window starts at 5 s, in the GOP of 3 s
+        assert_eq!(secs(&buffered), vec![3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn trimming_starts_on_a_keyframe() {
+        let mut buffered: VecDeque<Sample> = samples(5).into_iter().skip(1).collect();
+        RingBuffer::trim(
+            &mut buffered,
+            ClockTime::from_seconds(10),
+            ClockTime::from_seconds(5),
+        );
+        assert_eq!(secs(&buffered), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn trimming_keeps_the_last_gop() {
+        let mut buffered = samples(4);
+        RingBuffer::trim(&mut buffered, ClockTime::ZERO, ClockTime::from_seconds(4));
+        assert_eq!(secs(&buffered), vec![3, 4]);
+    }
+}
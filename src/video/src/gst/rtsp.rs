@@ -0,0 +1,118 @@
+use std::sync::Mutex;
+
+use gstreamer::glib;
+use gstreamer::prelude::Cast;
+use gstreamer::Element;
+use gstreamer_rtsp_server::prelude::{
+    RTSPMediaFactoryExt, RTSPMountPointsExt, RTSPServerExt, RTSPServerExtManual,
+};
+use gstreamer_rtsp_server::subclass::prelude::*;
+use gstreamer_rtsp_server::{gst_rtsp, RTSPMediaFactory, RTSPServer};
+use log::{error, info};
+
+use util::DecoderOptions;
+
+use super::{Error, GstreamerDecoder};
+use crate::{Decoder, Error as VideoError, VideoInput};
+
+/// Address the RTSP server listens on
+const RTSP_ADDRESS: &str = "0.0.0.0";
+
+/// Media factory that builds the elements of each media with the same builders as the
+/// pipeline, rather than from a launch description: what is set up in code, such as the
+/// latency of network sources or the dynamic linking of demuxers, is kept
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct MediaFactory {
+        /// The input and the options the elements are built from
+        pub(super) source: Mutex<Option<(VideoInput, DecoderOptions)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for MediaFactory {
+        const NAME: &'static str = "HcMediaFactory";
+        type Type = super::MediaFactory;
+        type ParentType = RTSPMediaFactory;
+    }
+
+    impl ObjectImpl for MediaFactory {}
+
+    impl RTSPMediaFactoryImpl for MediaFactory {
+        fn create_element(&self, _url: &gst_rtsp::RTSPUrl) -> Option<Element> {
+            let (input, opts) = self.source.lock().ok()?.clone()?;
+            match GstreamerDecoder::media_bin(input, opts) {
+                Ok(bin) => Some(bin),
+                Err(e) => {
+                    error!("Can't create the RTSP media: {}", e);
+                    None
+                }
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    /// Media factory building each media from an input and options
+    pub struct MediaFactory(ObjectSubclass<imp::MediaFactory>)
+        @extends RTSPMediaFactory;
+}
+
+impl MediaFactory {
+    fn new(input: VideoInput, opts: DecoderOptions) -> Self {
+        let factory: Self = glib::Object::new();
+        if let Ok(mut source) = factory.imp().source.lock() {
+            *source = Some((input, opts));
+        }
+        factory
+    }
+}
+
+impl GstreamerDecoder {
+    /// Build the elements of an RTSP media into a new bin: the source, filters and encoder,
+    /// up to the payloader named `pay0` that the server streams from
+    fn media_bin(input: VideoInput, opts: DecoderOptions) -> Result<Element, VideoError> {
+        let decoder_rc = Self::new(input)?;
+        let bin = gstreamer::Bin::new();
+        decoder_rc.lock().map_err(|_| VideoError::PoisonedLock)?.bin = bin.clone();
+        Self::build(decoder_rc, opts)?;
+        Ok(bin.upcast())
+    }
+
+    /// Publish the processed video at `rtsp://0.0.0.0:{port}{mount}` with a
+    /// [`gst-rtsp-server`](https://gstreamer.freedesktop.org/documentation/gst-rtsp-server/index.html?gi-language=c)
+    /// and serve clients until the process is stopped.
+    ///
+    /// The media factory builds the elements of each media again from the input and the
+    /// options of the built pipeline (source, filters, encoder and payloader). The media is
+    /// shared, so all the clients watch the same pipeline, which is started by the first client.
+    pub(super) fn serve_rtsp(&self, port: u16, mount: &str) -> Result<(), VideoError> {
+        let opts = self.options.clone().ok_or_else(|| {
+            VideoError::Gstreamer(Error::RtspServer("pipeline not built".to_string()))
+        })?;
+        let factory = MediaFactory::new(self.input.clone(), opts);
+        factory.set_shared(true);
+
+        let server = RTSPServer::new();
+        server.set_address(RTSP_ADDRESS);
+        server.set_service(&port.to_string());
+        server
+            .mount_points()
+            .ok_or_else(|| VideoError::Gstreamer(Error::RtspServer("no mount points".to_string())))?
+            .add_factory(mount, factory);
+        server.connect_client_connected(|_server, _client| info!("RTSP client connected"));
+
+        let source = server.attach(None).map_err(|e| {
+            VideoError::Gstreamer(Error::RtspServer(format!(
+                "can't listen on {}:{}: {}",
+                RTSP_ADDRESS, port, e
+            )))
+        })?;
+        info!("Serving rtsp://{}:{}{}", RTSP_ADDRESS, port, mount);
+
+        glib::MainLoop::new(None, false).run();
+        source.remove();
+        Ok(())
+    }
+}
@@ -0,0 +1,736 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use gstreamer::prelude::Cast;
+use gstreamer::Element;
+use gstreamer_app::AppSink;
+use log::{debug, info};
+
+use util::{
+    AnimationFormat, AnimationOptions, Container, FrameOptions, Output, OutputOptions, RawFormat,
+    SegmentFormat, SegmentOptions, SplitOptions, VideoFormat,
+};
+
+use super::frames::FrameExtractor;
+use super::rawfile::RawFileWriter;
+use super::registry::ElementRegistry;
+use super::ringbuffer::{EventRecorders, RingBuffer};
+use super::{require_element, Error, GstreamerDecoder, Preparation};
+use crate::Error as VideoError;
+
+/// RTP payload type announced for the H264 stream (first dynamic payload type)
+const RTP_PAYLOAD_TYPE: u32 = 96;
+
+/// Pixels per kbit/s of the rendition bitrates: 1280x720 gets x264enc's default 2048 kbit/s
+const PIXELS_PER_KBPS: i32 = 450;
+
+/// Where `hlscmafsink` comes from
+const HLSSINK3_PLUGIN: &str = "the hlssink3 plugin of gst-plugins-rs (gst-plugin-hlssink3)";
+
+/// Where `gifenc` comes from
+const GIF_PLUGIN: &str = "the gif plugin of gst-plugins-rs (gst-plugin-gif)";
+
+/// Kind of manifest of a segmented output
+#[derive(Copy, Clone, PartialEq)]
+enum Manifest {
+    Hls,
+    Dash,
+}
+
+impl GstreamerDecoder {
+    /// Create the steps that take the filtered video to `output`: its own scaling, if any,
+    /// the encoder (in `format`, unless the output overrides it) and the sink
+    pub(super) fn output_steps(
+        names: &mut ElementRegistry,
+        output: &OutputOptions,
+        format: VideoFormat,
+        fragments: &mut HashMap<String, String>,
+        recorders: &mut EventRecorders,
+        preparations: &mut Vec<Preparation>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let format = output.format.unwrap_or(format);
+        let mut steps = Self::change_res(names, output.width_height)?;
+        // Image outputs take raw frames, outputs with several renditions encode each of them
+        if !output.target.takes_raw_video() {
+            steps.extend(Self::encode(names, format)?);
+        }
+        steps.extend(Self::sink(
+            names,
+            &output.target,
+            format,
+            fragments,
+            recorders,
+            preparations,
+        )?);
+        Ok(steps)
+    }
+
+    /// Create the [`tee`](https://gstreamer.freedesktop.org/documentation/coreelements/tee.html?gi-language=c)
+    /// that feeds the filtered video to several outputs
+    pub(super) fn fanout(names: &mut ElementRegistry) -> Result<Element, VideoError> {
+        names
+            .make_with_role("tee", "tee")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))
+    }
+
+    /// Create the branch of the tee for `output`: a queue, so that each output runs in its own
+    /// thread, followed by the [output steps](Self::output_steps).
+    /// The queues of live outputs (screen, network) drop old frames rather than block the tee
+    /// when their output falls behind; recordings never lose frames.
+    pub(super) fn branch(
+        names: &mut ElementRegistry,
+        output: &OutputOptions,
+        format: VideoFormat,
+        fragments: &mut HashMap<String, String>,
+        recorders: &mut EventRecorders,
+        preparations: &mut Vec<Preparation>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let live = matches!(output.target, Output::Screen | Output::Rtp { .. });
+        let mut steps = vec![names
+            .make_with_role("queue", "branch")
+            .property_from_str_if_some("leaky", live.then_some("downstream"))
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?];
+        steps.extend(Self::output_steps(
+            names,
+            output,
+            format,
+            fragments,
+            recorders,
+            preparations,
+        )?);
+        Ok(steps)
+    }
+
+    /// Create the steps that take the encoded video to `output`.
+    /// Outputs with several renditions take the raw video and encode it themselves, in `format`.
+    /// Steps parsed from a launch description are recorded in `fragments`, and the files to
+    /// write before the pipeline starts in `preparations`.
+    pub(super) fn sink(
+        names: &mut ElementRegistry,
+        output: &Output,
+        format: VideoFormat,
+        fragments: &mut HashMap<String, String>,
+        recorders: &mut EventRecorders,
+        preparations: &mut Vec<Preparation>,
+    ) -> Result<Vec<Element>, VideoError> {
+        match output {
+            Output::Screen => Self::screenout(names),
+            Output::Rtp { host, port, sdp } => {
+                if let Some(sdp) = sdp {
+                    preparations.push(Preparation::File(sdp.clone(), Self::sdp(host, *port)));
+                }
+                Self::rtpout(names, host, *port)
+            }
+            Output::Rtsp { .. } => Self::rtsppay(names),
+            Output::File {
+                path,
+                container,
+                split,
+            } => Self::fileout(names, path, *container, *split),
+            Output::EventRecording {
+                path,
+                container,
+                pre_secs,
+                post_secs,
+            } => Self::eventout(names, path, *container, *pre_secs, *post_secs, recorders),
+            Output::Frames(frames) => Self::framesout(names, frames),
+            Output::Animation(anim) => Self::animationout(names, anim),
+            Output::Raw { path, format } => Self::rawout(names, path, *format),
+            Output::Stdout { container } => Self::stdout(names, *container),
+            Output::Hls(seg) => {
+                Self::segmented(names, Manifest::Hls, seg, format, fragments, preparations)
+            }
+            Output::Dash(seg) => {
+                Self::segmented(names, Manifest::Dash, seg, format, fragments, preparations)
+            }
+        }
+    }
+
+    /// Decode the encoded video again and show it in a window
+    fn screenout(names: &mut ElementRegistry) -> Result<Vec<Element>, VideoError> {
+        Ok(vec![
+            names
+                .make("avdec_h264")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("xvimagesink", "sink")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// Record the encoded video to a file:
+    ///
+    /// ```text
+    /// h264parse ! {mp4mux|matroskamux|mpegtsmux} ! filesink location={path}
+    /// ```
+    ///
+    /// or, to split the recording into several files, each starting on a keyframe:
+    ///
+    /// ```text
+    /// h264parse ! splitmuxsink location={path pattern} muxer-factory={mp4mux|matroskamux|mpegtsmux}
+    /// ```
+    ///
+    /// `splitmuxsink` announces each finished file, which is reported as [`Event::FileClosed`](crate::Event::FileClosed).
+    fn fileout(
+        names: &mut ElementRegistry,
+        path: &Path,
+        container: Container,
+        split: Option<SplitOptions>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let muxer = Self::muxer(container);
+        let mut steps = vec![names
+            .make("h264parse")
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?];
+
+        match split {
+            None => {
+                steps.push(
+                    names
+                        .make_with_role(muxer, "muxer")
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+                steps.push(
+                    names
+                        .make_with_role("filesink", "sink")
+                        .property("location", path.to_string_lossy().to_string())
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+            }
+            Some(split) => {
+                let location = Self::split_pattern(path);
+                info!("Recording to {}", location);
+                steps.push(
+                    names
+                        .make_with_role("splitmuxsink", "sink")
+                        .property("location", location)
+                        .property("muxer-factory", muxer)
+                        .property_if_some(
+                            "max-size-time",
+                            split.max_time_secs.map(|secs| secs * 1_000_000_000),
+                        )
+                        .property_if_some(
+                            "max-size-bytes",
+                            split.max_size_mb.map(|mb| mb * 1_000_000),
+                        )
+                        // Ask the encoder for a keyframe when a file is due, rather than
+                        // waiting for the next one; only possible with time based splitting
+                        .property("send-keyframe-requests", split.max_size_mb.is_none())
+                        .build()
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                );
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Keep the last `pre_secs` of encoded video in a [`RingBuffer`], which records them with the
+    /// following `post_secs` to a new file each time the recorders' trigger fires:
+    ///
+    /// ```text
+    /// h264parse config-interval=-1 ! appsink
+    /// ```
+    ///
+    /// SPS/PPS are repeated with every keyframe, so that any keyframe can start a recording.
+    fn eventout(
+        names: &mut ElementRegistry,
+        path: &Path,
+        container: Container,
+        pre_secs: u64,
+        post_secs: u64,
+        recorders: &mut EventRecorders,
+    ) -> Result<Vec<Element>, VideoError> {
+        let sink = AppSink::builder()
+            .name(names.name_for_role("appsink", "event-buffer"))
+            .caps(
+                &gstreamer::Caps::builder("video/x-h264")
+                    .field("stream-format", "byte-stream")
+                    .field("alignment", "au")
+                    .build(),
+            )
+            .sync(false)
+            .build();
+        recorders.buffers.push(RingBuffer::attach(
+            &sink,
+            path,
+            Self::muxer(container),
+            pre_secs,
+            post_secs,
+            &recorders.trigger,
+        ));
+
+        Ok(vec![
+            names
+                .make("h264parse")
+                .property("config-interval", -1i32)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            sink.upcast(),
+        ])
+    }
+
+    /// Write the selected frames to image files with a [`FrameExtractor`], fed by an `appsink`.
+    /// The frames are converted to PNG or JPEG as they are extracted, so the sink takes the
+    /// raw video as it leaves the filters.
+    fn framesout(
+        names: &mut ElementRegistry,
+        frames: &FrameOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        let sink = AppSink::builder()
+            .name(names.name_for_role("appsink", "sink"))
+            .caps(&gstreamer::Caps::builder("video/x-raw").build())
+            .sync(false)
+            .build();
+        FrameExtractor::attach(&sink, frames);
+        Ok(vec![sink.upcast()])
+    }
+
+    /// Write the encoded video to stdout, in a container that can be played while it is
+    /// written, for the next program in a shell pipeline:
+    ///
+    /// ```text
+    /// h264parse ! {mpegtsmux|matroskamux streamable=true} ! fdsink fd=1
+    /// ```
+    fn stdout(
+        names: &mut ElementRegistry,
+        container: Container,
+    ) -> Result<Vec<Element>, VideoError> {
+        let mut muxer = names.make_with_role(Self::muxer(container), "muxer");
+        if let Container::Matroska = container {
+            muxer = muxer.property("streamable", true);
+        }
+        Ok(vec![
+            names
+                .make("h264parse")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            muxer
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("fdsink", "sink")
+                .property("fd", 1i32)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// Write the decoded and filtered frames to a headerless raw file, without encoding, so that
+    /// it can be compared byte for byte with a reference:
+    ///
+    /// ```text
+    /// videoconvert ! appsink caps=video/x-raw,format={format}
+    /// ```
+    ///
+    /// The [`RawFileWriter`] fed by the `appsink` packs the rows, which gstreamer pads to a
+    /// multiple of 4 bytes, so the file matches reference outputs whatever the resolution.
+    fn rawout(
+        names: &mut ElementRegistry,
+        path: &Path,
+        format: RawFormat,
+    ) -> Result<Vec<Element>, VideoError> {
+        let sink = AppSink::builder()
+            .name(names.name_for_role("appsink", "sink"))
+            .caps(
+                &gstreamer::Caps::builder("video/x-raw")
+                    .field("format", format.to_string().to_uppercase())
+                    .build(),
+            )
+            .sync(false)
+            .build();
+        RawFileWriter::attach(&sink, path, format);
+        Ok(vec![
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            sink.upcast(),
+        ])
+    }
+
+    /// Write the raw video to an animated GIF or WebP, at a reduced frame rate and scaled down
+    /// to the maximum width, if wider:
+    ///
+    /// ```text
+    /// videorate ! video/x-raw,framerate={fps}/1 ! videoscale ! video/x-raw,width=[1,{max width}],pixel-aspect-ratio=1/1 !
+    ///     videoconvert ! {gifenc repeat=-1|webpenc animated=true} ! filesink location={path}
+    /// ```
+    ///
+    /// [`gifenc`](https://gstreamer.freedesktop.org/documentation/gif/index.html?gi-language=c)
+    /// generates an optimized palette for each frame and only stores what changed since the
+    /// previous one; [`webpenc`](https://gstreamer.freedesktop.org/documentation/webp/webpenc.html?gi-language=c)
+    /// writes the whole animation at the end of the stream. Both loop forever.
+    ///
+    /// `gifenc` isn't part of the distribution packages, so GIF outputs are refused up front
+    /// when it is missing.
+    fn animationout(
+        names: &mut ElementRegistry,
+        anim: &AnimationOptions,
+    ) -> Result<Vec<Element>, VideoError> {
+        let mut size = gstreamer::Caps::builder("video/x-raw")
+            .field("pixel-aspect-ratio", gstreamer::Fraction::new(1, 1));
+        if let Some(max_width) = anim.max_width {
+            size = size.field("width", gstreamer::IntRange::new(1, max_width as i32));
+        }
+        if matches!(anim.format, AnimationFormat::Gif) {
+            require_element("gifenc", GIF_PLUGIN)?;
+        }
+        let encoder = match anim.format {
+            AnimationFormat::Gif => names
+                .make_with_role("gifenc", "encoder")
+                .property("repeat", -1i32),
+            AnimationFormat::WebP => names
+                .make_with_role("webpenc", "encoder")
+                .property("animated", true)
+                .property("animation-loops", 0u32),
+        };
+
+        Ok(vec![
+            names
+                .make("videorate")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("capsfilter")
+                .property(
+                    "caps",
+                    gstreamer::Caps::builder("video/x-raw")
+                        .field("framerate", gstreamer::Fraction::new(anim.fps as i32, 1))
+                        .build(),
+                )
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("videoscale")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("capsfilter")
+                .property("caps", size.build())
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make("videoconvert")
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            encoder
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("filesink", "sink")
+                .property("location", anim.path.to_string_lossy().to_string())
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// The muxer writing `container`
+    fn muxer(container: Container) -> &'static str {
+        match container {
+            Container::Mp4 => "mp4mux",
+            Container::Matroska => "matroskamux",
+            Container::MpegTs => "mpegtsmux",
+        }
+    }
+
+    /// The file name pattern of a split recording: `path` itself if it already has a
+    /// `printf`-style placeholder, `path` with `_%05d` before the extension otherwise
+    /// (`rec.mp4` becomes `rec_%05d.mp4`)
+    fn split_pattern(path: &Path) -> String {
+        let path_str = path.to_string_lossy();
+        if path_str.contains('%') {
+            return path_str.to_string();
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{}_%05d.{}", stem, ext.to_string_lossy()),
+            None => format!("{}_%05d", stem),
+        };
+        path.with_file_name(name).to_string_lossy().to_string()
+    }
+
+    /// Packetize the encoded video as RTP
+    /// ([RFC 6184](https://datatracker.ietf.org/doc/html/rfc6184)) and send it over UDP.
+    /// SPS/PPS are repeated with every keyframe so that receivers can join at any time.
+    ///
+    /// ```text
+    /// rtph264pay config-interval=-1 pt=96 ! udpsink host={host} port={port}
+    /// ```
+    fn rtpout(
+        names: &mut ElementRegistry,
+        host: &str,
+        port: u16,
+    ) -> Result<Vec<Element>, VideoError> {
+        Ok(vec![
+            names
+                .make_with_role("rtph264pay", "payloader")
+                .property("config-interval", -1i32)
+                .property("pt", RTP_PAYLOAD_TYPE)
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            names
+                .make_with_role("udpsink", "sink")
+                .property("host", host)
+                .property("port", i32::from(port))
+                .build()
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+        ])
+    }
+
+    /// Payload the encoded video for the RTSP server, which streams whatever the element named
+    /// `pay0` of its media payloads
+    fn rtsppay(names: &mut ElementRegistry) -> Result<Vec<Element>, VideoError> {
+        Ok(vec![names
+            .make_named("rtph264pay", "pay0", "payloader")
+            .property("config-interval", -1i32)
+            .property("pt", RTP_PAYLOAD_TYPE)
+            .build()
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?])
+    }
+
+    /// SDP description of the RTP stream, which players such as
+    /// `ffplay -protocol_whitelist file,udp,rtp stream.sdp` or VLC can open
+    fn sdp(host: &str, port: u16) -> String {
+        format!(
+            "v=0\r\n\
+             o=- 0 0 IN IP4 {host}\r\n\
+             s=harmanchallenge\r\n\
+             c=IN IP4 {host}\r\n\
+             t=0 0\r\n\
+             m=video {port} RTP/AVP {pt}\r\n\
+             a=rtpmap:{pt} H264/90000\r\n\
+             a=fmtp:{pt} packetization-mode=1\r\n",
+            host = host,
+            port = port,
+            pt = RTP_PAYLOAD_TYPE,
+        )
+    }
+
+    /// Write the video as an HLS playlist or a DASH manifest, and its segments, with
+    /// [`hlssink2`](https://gstreamer.freedesktop.org/documentation/hls/hlssink2.html?gi-language=c) (TS segments),
+    /// [`hlscmafsink`](https://gstreamer.freedesktop.org/documentation/hlssink3/hlscmafsink.html?gi-language=c)
+    /// (fragmented MP4 segments, from the Rust plugins) or
+    /// [`dashsink`](https://gstreamer.freedesktop.org/documentation/dash/dashsink.html?gi-language=c).
+    ///
+    /// The steps are a single bin parsed from a launch description. With several renditions,
+    /// the video is split by a `tee` and each branch is scaled and encoded on its own:
+    /// HLS renditions are written to `<width>x<height>/` subdirectories, next to a master
+    /// playlist, and DASH renditions are the representations of a single manifest. The
+    /// subdirectories and the master playlist are left to `preparations`. `hlscmafsink` isn't
+    /// part of the distribution packages, so fMP4 HLS outputs are refused up front when it is
+    /// missing.
+    ///
+    /// ```text
+    /// tee name=tee-0
+    ///     tee-0. ! queue ! videoscale ! video/x-raw,width=1280,height=720 ! x264enc ! h264parse ! hlssink2
+    ///     tee-0. ! queue ! videoscale ! video/x-raw,width=640,height=360 ! x264enc ! h264parse ! hlssink2
+    /// ```
+    fn segmented(
+        names: &mut ElementRegistry,
+        manifest: Manifest,
+        seg: &SegmentOptions,
+        format: VideoFormat,
+        fragments: &mut HashMap<String, String>,
+        preparations: &mut Vec<Preparation>,
+    ) -> Result<Vec<Element>, VideoError> {
+        let dir = seg.manifest.parent().unwrap_or(Path::new(""));
+        let segment_format = seg.segment_format.unwrap_or(match manifest {
+            Manifest::Hls => SegmentFormat::Ts,
+            Manifest::Dash => SegmentFormat::Fmp4,
+        });
+        if manifest == Manifest::Hls && matches!(segment_format, SegmentFormat::Fmp4) {
+            require_element("hlscmafsink", HLSSINK3_PLUGIN)?;
+        }
+
+        // The entry chain goes first so that the bin's sink pad is the first element's,
+        // and the bin can be inlined as-is in a launch line
+        let mut chains = vec![];
+        let dashsink = match manifest {
+            Manifest::Dash => Some(names.name_for_role("dashsink", "sink")),
+            Manifest::Hls => None,
+        };
+
+        if seg.renditions.is_empty() {
+            let sink = match &dashsink {
+                Some(name) => format!("{}.", name),
+                None => Self::hls_sink(names, seg, segment_format, dir, &seg.manifest),
+            };
+            chains.push(format!(
+                "h264parse name={} ! {}",
+                names.unique_name("h264parse"),
+                sink
+            ));
+        } else {
+            let tee = names.name_for_role("tee", "tee");
+            chains.push(format!("tee name={}", tee));
+            let mut variants = vec![];
+            for &(width, height) in &seg.renditions {
+                let kbps = (width * height / PIXELS_PER_KBPS).max(1);
+                let encoder = match format {
+                    VideoFormat::H264 => format!(
+                        "x264enc name={} tune=zerolatency bitrate={}",
+                        names.name_for_role("x264enc", "encoder"),
+                        kbps
+                    ),
+                };
+                let sink = match &dashsink {
+                    Some(name) => format!("{}.", name),
+                    None => {
+                        let subdir = format!("{}x{}", width, height);
+                        let rendition_dir = dir.join(&subdir);
+                        preparations.push(Preparation::Dir(rendition_dir.clone()));
+                        variants.push((width, height, kbps, subdir));
+                        Self::hls_sink(
+                            names,
+                            seg,
+                            segment_format,
+                            &rendition_dir,
+                            &rendition_dir.join("playlist.m3u8"),
+                        )
+                    }
+                };
+                chains.push(format!(
+                    "{}. ! queue name={} ! videoscale name={} ! video/x-raw,width={},height={} ! {} ! h264parse name={} ! {}",
+                    tee,
+                    names.unique_name("queue"),
+                    names.name_for_role("videoscale", "scaler"),
+                    width,
+                    height,
+                    encoder,
+                    names.unique_name("h264parse"),
+                    sink
+                ));
+            }
+            if manifest == Manifest::Hls {
+                preparations.push(Preparation::File(
+                    seg.manifest.clone(),
+                    Self::master_playlist(segment_format, &variants),
+                ));
+            }
+        }
+
+        if let Some(name) = dashsink {
+            let mpd = seg
+                .manifest
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
+            chains.push(format!(
+                "dashsink name={} mpd-filename=\"{}\" mpd-root-path=\"{}\" target-duration={} muxer={}",
+                name,
+                mpd,
+                dir.display(),
+                seg.segment_duration,
+                match segment_format {
+                    SegmentFormat::Ts => "ts",
+                    SegmentFormat::Fmp4 => "dash-mp4",
+                }
+            ));
+        }
+
+        let desc = chains.join("  ");
+        debug!("Segmented output: {}", desc);
+        let name = names.name_for_role("outputbin", "output");
+        let bin = gstreamer::parse::bin_from_description_with_name(&desc, true, &name)
+            .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+        fragments.insert(name, desc);
+        Ok(vec![bin.upcast()])
+    }
+
+    /// Describe the HLS sink writing `playlist` and its segments to `dir`
+    fn hls_sink(
+        names: &mut ElementRegistry,
+        seg: &SegmentOptions,
+        segment_format: SegmentFormat,
+        dir: &Path,
+        playlist: &Path,
+    ) -> String {
+        // Keep some segments that dropped out of the playlist, for slow clients
+        let max_files = seg.playlist_length * 2;
+        match segment_format {
+            SegmentFormat::Ts => format!(
+                "hlssink2 name={} location=\"{}\" playlist-location=\"{}\" target-duration={} playlist-length={} max-files={}",
+                names.name_for_role("hlssink2", "sink"),
+                dir.join("segment%05d.ts").display(),
+                playlist.display(),
+                seg.segment_duration,
+                seg.playlist_length,
+                max_files
+            ),
+            SegmentFormat::Fmp4 => format!(
+                "hlscmafsink name={} location=\"{}\" init-location=\"{}\" playlist-location=\"{}\" target-duration={} playlist-length={} max-num-segment-files={}",
+                names.name_for_role("hlscmafsink", "sink"),
+                dir.join("segment%05d.m4s").display(),
+                dir.join("init%05d.mp4").display(),
+                playlist.display(),
+                seg.segment_duration,
+                seg.playlist_length,
+                max_files
+            ),
+        }
+    }
+
+    /// HLS master playlist listing the `variants` (width, height, kbit/s, playlist directory),
+    /// so that players can switch between renditions
+    fn master_playlist(
+        segment_format: SegmentFormat,
+        variants: &[(i32, i32, i32, String)],
+    ) -> String {
+        // fMP4 segments need EXT-X-MAP, introduced in version 7
+        let version = match segment_format {
+            SegmentFormat::Ts => 3,
+            SegmentFormat::Fmp4 => 7,
+        };
+        let mut playlist = format!("#EXTM3U\n#EXT-X-VERSION:{}\n", version);
+        for (width, height, kbps, dir) in variants {
+            playlist.push_str(&format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{}\n{}/playlist.m3u8\n",
+                kbps * 1000,
+                width,
+                height,
+                dir
+            ));
+        }
+        playlist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_patterns_number_the_files() {
+        let pattern = |path: &str| GstreamerDecoder::split_pattern(Path::new(path));
+        assert_eq!(pattern("rec.mp4"), "rec_%05d.mp4");
+        assert_eq!(pattern("out/rec.mkv"), "out/rec_%05d.mkv");
+        assert_eq!(pattern("out/rec"), "out/rec_%05d");
+        assert_eq!(pattern("out/rec.tar.ts"), "out/rec.tar_%05d.ts");
+    }
+
+    #[test]
+    fn split_patterns_keep_placeholders() {
+        let pattern = |path: &str| GstreamerDecoder::split_pattern(Path::new(path));
+        assert_eq!(pattern("out/part%03d.mp4"), "out/part%03d.mp4");
+    }
+
+    #[test]
+    fn sdp_describes_the_rtp_stream() {
+        let sdp = GstreamerDecoder::sdp("192.168.1.20", 5004);
+        assert!(sdp.starts_with("v=0\r\n"));
+        assert!(sdp.contains("c=IN IP4 192.168.1.20\r\n"));
+        assert!(sdp.contains("m=video 5004 RTP/AVP 96\r\n"));
+        assert!(sdp.contains("a=rtpmap:96 H264/90000\r\n"));
+        assert!(sdp.ends_with("\r\n"));
+    }
+}
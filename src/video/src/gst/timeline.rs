@@ -0,0 +1,370 @@
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use gstreamer::prelude::{Cast, ElementExt, GstObjectExt};
+use gstreamer::{glib, Caps, ClockTime, Fraction};
+use gstreamer_editing_services as ges;
+use gstreamer_editing_services::prelude::{
+    GESPipelineExt, GESTrackExt, LayerExt, TestClipExt, TimelineElementExt, TimelineExt,
+    TransitionClipExt,
+};
+use gstreamer_pbutils::{EncodingAudioProfile, EncodingContainerProfile, EncodingVideoProfile};
+use log::{debug, error, info, warn};
+
+use util::{ConcatOptions, Container, DecoderOptions, Output, TransitionKind, VideoFormat};
+
+use super::{Error, GstreamerDecoder, Interrupt, Preparation};
+use crate::Error as VideoError;
+use crate::{Event, VideoInput};
+
+/// Implements the [`Decoder`](crate::Decoder) trait with a
+/// [GES](https://gstreamer.freedesktop.org/documentation/gst-editing-services/index.html?gi-language=c)
+/// timeline, for files played in a row with transitions between them.
+///
+/// The files are laid out one after another on a layer with automatic transitions, so that
+/// overlapping files crossfade, video and audio alike. Wipes are crossfades whose video
+/// transition is changed to a left to right bar wipe, and dips to black go through a short,
+/// silent black clip. The filters are applied by an effect clip spanning the whole timeline,
+/// on the layer above.
+///
+/// Only the screen and file outputs are supported: the timeline is previewed (with its sound)
+/// or rendered, with h264 video and AAC audio.
+pub struct TimelineDecoder {
+    timeline: ges::Timeline,
+    /// Layer above the files, with the effect clip applying the filters
+    effects: ges::Layer,
+    pipeline: ges::Pipeline,
+    /// Resolution and framerate of the timeline: the first file's, unless others are requested
+    format: ((i32, i32), Fraction),
+    /// The files and transitions in playing order, as listed by [`describe`](crate::Decoder::describe)
+    layout: Vec<String>,
+    /// Launch description of the effect applying the filters, if any
+    effect: Option<String>,
+    /// Where the timeline goes, once built
+    output: Output,
+}
+
+/// `file://` URI of `path`, which doesn't have to exist
+fn file_uri(path: &Path) -> Result<String, VideoError> {
+    let path = env::current_dir().map_err(VideoError::Io)?.join(path);
+    glib::filename_to_uri(&path, None)
+        .map(|uri| uri.to_string())
+        .map_err(|e| VideoError::Gstreamer(Error::Glib(e)))
+}
+
+/// Encoding profile of the rendered file: h264 video and AAC audio in `container`
+fn encoding_profile(container: Container, format: VideoFormat) -> EncodingContainerProfile {
+    let container_caps = match container {
+        Container::Mp4 => Caps::builder("video/quicktime").field("variant", "iso"),
+        Container::Matroska => Caps::builder("video/x-matroska"),
+        Container::MpegTs => Caps::builder("video/mpegts")
+            .field("systemstream", true)
+            .field("packetsize", 188i32),
+    }
+    .build();
+    let video_caps = match format {
+        VideoFormat::H264 => Caps::builder("video/x-h264").build(),
+    };
+    let audio_caps = Caps::builder("audio/mpeg")
+        .field("mpegversion", 4i32)
+        .build();
+    EncodingContainerProfile::builder(&container_caps)
+        .add_profile(EncodingVideoProfile::builder(&video_caps).build())
+        .add_profile(EncodingAudioProfile::builder(&audio_caps).build())
+        .build()
+}
+
+impl TimelineDecoder {
+    /// The interrupt of the timeline's pipeline, to end the rendering cleanly, e.g. on Ctrl-C
+    pub fn interrupt(&self) -> Interrupt {
+        Interrupt(self.pipeline.clone().upcast())
+    }
+
+    /// A silent black clip of `length` from `start`, for a dip to black
+    fn black(start: ClockTime, length: ClockTime) -> Result<ges::TestClip, VideoError> {
+        let black = ges::TestClip::new().ok_or_else(|| {
+            VideoError::Gstreamer(Error::Timeline("can't create a black clip".to_string()))
+        })?;
+        black.set_vpattern(ges::VideoTestPattern::Black);
+        black.set_mute(true);
+        black.set_start(start);
+        black.set_duration(length);
+        Ok(black)
+    }
+
+    /// Put the files on `layer` one after another, overlapping by the length of the crossfades
+    /// and wipes, and return the layout: when each file and transition starts
+    fn lay_out(
+        layer: &ges::Layer,
+        fnames: &[String],
+        opts: &ConcatOptions,
+    ) -> Result<Vec<String>, VideoError> {
+        let mut layout = vec![];
+        let mut wipes = vec![];
+        let mut end = ClockTime::ZERO;
+        let mut previous = ClockTime::ZERO;
+        for (n, fname) in fnames.iter().enumerate() {
+            let clip = ges::UriClip::new(&file_uri(Path::new(fname))?)
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            let duration = clip.duration();
+            let mut start = end;
+            if n > 0 {
+                let transition = opts.transition(n - 1);
+                let length = ClockTime::from_seconds_f64(transition.duration_secs);
+                if length >= duration.min(previous) {
+                    return Err(VideoError::Gstreamer(Error::Timeline(format!(
+                        "the {} between {} and {} is longer than one of them",
+                        transition,
+                        fnames[n - 1],
+                        fname
+                    ))));
+                }
+                match transition.kind {
+                    TransitionKind::Cut => (),
+                    TransitionKind::Crossfade => start = end - length,
+                    TransitionKind::Wipe => {
+                        start = end - length;
+                        wipes.push(start);
+                    }
+                    TransitionKind::DipToBlack => layer
+                        .add_clip(&Self::black(end - length / 2, length)?)
+                        .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+                }
+                layout.push(format!("{:.3} {}", start, transition));
+            }
+            clip.set_start(start);
+            layer
+                .add_clip(&clip)
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            layout.push(format!("{:.3} {} ({:.3})", start, fname, duration));
+            end = start + duration;
+            previous = duration;
+        }
+
+        // The automatic transitions are crossfades; the wipes are told apart by where they start
+        for clip in layer.clips() {
+            if let Ok(transition) = clip.downcast::<ges::TransitionClip>() {
+                if wipes.contains(&transition.start()) {
+                    debug!("Wipe at {}", transition.start());
+                    transition.set_vtype(ges::VideoStandardTransitionType::BarWipeLr);
+                }
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Launch description of the effect applying the requested filters, if any, in the same
+    /// order as the regular pipeline does
+    fn effect_description(opts: &DecoderOptions) -> Option<String> {
+        let mut filters = vec![];
+        if opts.invert {
+            filters.push("coloreffects preset=xray".to_string());
+        }
+        if opts.flip {
+            filters.push("videoflip video-direction=horiz".to_string());
+        }
+        filters.extend(opts.filter_bins.iter().cloned());
+        (!filters.is_empty()).then(|| filters.join(" ! videoconvert ! "))
+    }
+}
+
+impl crate::Decoder for TimelineDecoder {
+    /// Lay out the files of a [`VideoInput::Concat`] on a new timeline
+    fn new(input: VideoInput) -> Result<Arc<Mutex<Self>>, VideoError> {
+        let VideoInput::Concat(fnames, opts) = input else {
+            return Err(VideoError::Gstreamer(Error::Timeline(
+                "only files played in a row can be put on a timeline".to_string(),
+            )));
+        };
+        gstreamer::init().map_err(|e| VideoError::Gstreamer(Error::Glib(e)))?;
+        ges::init().map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+
+        let clip = GstreamerDecoder::probe_clip(&fnames[0])?;
+        let width_height = clip.width_height;
+        let framerate = opts
+            .framerate
+            .map(|fps| Fraction::new(fps, 1))
+            .unwrap_or(clip.framerate);
+
+        let timeline = ges::Timeline::new_audio_video();
+        // Layers are stacked in the order they are added, the first one on top
+        let effects = timeline.append_layer();
+        let layer = timeline.append_layer();
+        layer.set_auto_transition(true);
+        let layout = Self::lay_out(&layer, &fnames, &opts)?;
+
+        let pipeline = ges::Pipeline::new();
+        pipeline
+            .set_timeline(&timeline)
+            .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+        // GES objects aren't meant to be shared between threads, and aren't: the Arc<Mutex> is
+        // only what the Decoder trait hands out
+        #[allow(clippy::arc_with_non_send_sync)]
+        Ok(Arc::new(Mutex::new(TimelineDecoder {
+            timeline,
+            effects,
+            pipeline,
+            format: (width_height, framerate),
+            layout,
+            effect: None,
+            output: Output::Screen,
+        })))
+    }
+
+    /// Apply the resolution, framerate and filters to the whole timeline, and set it up to be
+    /// previewed on the screen or rendered to a file
+    fn build(self_rc: Arc<Mutex<Self>>, opts: DecoderOptions) -> Result<(), VideoError> {
+        let mut lock = self_rc.lock();
+        let decoder = lock.as_deref_mut().map_err(|_| VideoError::PoisonedLock)?;
+
+        let [output] = opts.outputs.as_slice() else {
+            return Err(VideoError::Gstreamer(Error::Timeline(
+                "files joined with transitions go to a single output".to_string(),
+            )));
+        };
+        match &output.target {
+            Output::Screen => decoder
+                .pipeline
+                .set_mode(ges::PipelineFlags::FULL_PREVIEW)
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?,
+            Output::File {
+                path,
+                container,
+                split: None,
+            } => {
+                decoder
+                    .pipeline
+                    .set_render_settings(
+                        &file_uri(path)?,
+                        &encoding_profile(*container, output.format.unwrap_or(opts.format)),
+                    )
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+                decoder
+                    .pipeline
+                    .set_mode(ges::PipelineFlags::RENDER)
+                    .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            }
+            other => {
+                return Err(VideoError::Gstreamer(Error::Timeline(format!(
+                    "files joined with transitions can only go to the screen or to a file, not {}",
+                    other
+                ))))
+            }
+        }
+        decoder.output = output.target.clone();
+
+        let ((width, height), framerate) = decoder.format;
+        let (width, height) = output
+            .width_height
+            .or(opts.width_height)
+            .unwrap_or((width, height));
+        decoder.format = ((width, height), framerate);
+        let caps = Caps::builder("video/x-raw")
+            .field("width", width)
+            .field("height", height)
+            .field("framerate", framerate)
+            .build();
+        for track in decoder.timeline.tracks() {
+            if track.track_type() == ges::TrackType::VIDEO {
+                track.set_restriction_caps(&caps);
+            }
+        }
+
+        if let Some(desc) = Self::effect_description(&opts) {
+            let effect = ges::EffectClip::new(Some(&desc), None).ok_or_else(|| {
+                VideoError::Gstreamer(Error::Timeline(format!("can't create effect {}", desc)))
+            })?;
+            effect.set_start(ClockTime::ZERO);
+            effect.set_duration(decoder.timeline.duration());
+            decoder
+                .effects
+                .add_clip(&effect)
+                .map_err(|e| VideoError::Gstreamer(Error::GlibBool(e)))?;
+            decoder.effect = Some(desc);
+        }
+        if opts.start_secs.is_some() || opts.duration_secs.is_some() {
+            warn!("--start and --duration are ignored on a timeline");
+        }
+        decoder.timeline.commit();
+        Ok(())
+    }
+
+    /// A timeline has no `gst-launch-1.0` equivalent: list the files and transitions in playing
+    /// order instead, with the effect and the output
+    fn describe(&self) -> String {
+        let ((width, height), framerate) = self.format;
+        let mut lines = vec![format!(
+            "timeline at {}x{}, {} fps, to {}",
+            width, height, framerate, self.output
+        )];
+        lines.extend(self.layout.iter().map(|entry| format!("  {}", entry)));
+        if let Some(effect) = &self.effect {
+            lines.push(format!("  effect over the whole timeline: {}", effect));
+        }
+        lines.join("\n")
+    }
+
+    /// Preview or render the timeline, until its end
+    fn run(&mut self) -> Result<(), VideoError> {
+        info!(
+            "Starting timeline of {} ({:.3})",
+            self.pipeline.name(),
+            self.timeline.duration()
+        );
+        if let Some(dir) = self.output.dir() {
+            Preparation::Dir(dir.to_path_buf()).apply()?;
+        }
+        let pipeline = self.pipeline.upcast_ref::<gstreamer::Pipeline>();
+        pipeline
+            .set_state(gstreamer::State::Playing)
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+
+        let bus = pipeline.bus().ok_or(VideoError::Gstreamer(Error::Bus))?;
+        let mut failure = None;
+        for msg in bus.iter_timed(ClockTime::NONE) {
+            use gstreamer::MessageView;
+
+            match msg.view() {
+                MessageView::Eos(..) => {
+                    info!("End of timeline");
+                    break;
+                }
+                MessageView::Error(err) => {
+                    error!(
+                        "Error from {}: {} ({})",
+                        err.src()
+                            .map(|s| s.path_string().to_string())
+                            .unwrap_or_default(),
+                        err.error(),
+                        err.debug().unwrap_or_default()
+                    );
+                    failure = Some(err.error().to_string());
+                    break;
+                }
+                MessageView::Warning(w) => {
+                    warn!(
+                        "Warning from {}: {} ({})",
+                        w.src()
+                            .map(|s| s.path_string().to_string())
+                            .unwrap_or_default(),
+                        w.error(),
+                        w.debug().unwrap_or_default()
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        pipeline
+            .set_state(gstreamer::State::Null)
+            .map_err(|e| VideoError::Gstreamer(Error::PipelineStateChange(e)))?;
+        match failure {
+            Some(e) => Err(VideoError::Gstreamer(Error::Pipeline(e))),
+            None => Ok(()),
+        }
+    }
+
+    /// No events are reported while a timeline plays
+    fn on_event<F: Fn(&Event) + Send + 'static>(&mut self, _handler: F) {}
+}
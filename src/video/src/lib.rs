@@ -17,10 +17,16 @@
 //! Under the hood, the crate uses [`gstreamer`](https://gstreamer.freedesktop.org/).
 
 use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use util::DecoderOptions;
+use util::{
+    ConcatOptions, ContactSheetOptions, DecoderOptions, ImageSequenceOptions, MosaicOptions,
+    NetworkOptions, PipOptions, RawOptions, TestPatternOptions, WebcamOptions,
+};
 
 /// Gstreamer based implementation
 pub mod gst;
@@ -32,6 +38,8 @@ pub enum Error {
     Gstreamer(gst::Error),
     /// Mutex poisoned
     PoisonedLock,
+    /// I/O error
+    Io(io::Error),
 }
 
 impl Display for Error {
@@ -39,13 +47,151 @@ impl Display for Error {
         match self {
             Error::Gstreamer(e) => write!(f, "Gstreamer error: {}", e),
             Error::PoisonedLock => write!(f, "Mutex poisoned"),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
 
+#[derive(Clone)]
 pub enum VideoInput {
     File(String),
-    Webcam,
+    Webcam(WebcamOptions),
+    TestPattern(TestPatternOptions),
+    Uri(String, NetworkOptions),
+    ImageSequence(String, ImageSequenceOptions),
+    Raw(String, RawOptions),
+    Stdin,
+    Concat(Vec<String>, ConcatOptions),
+    PictureInPicture(Box<VideoInput>, PipOptions),
+    Mosaic(Vec<VideoInput>, MosaicOptions),
+}
+
+impl Display for VideoInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoInput::File(fname) => write!(f, "file {}", fname),
+            VideoInput::Webcam(opts) => write!(
+                f,
+                "webcam {}",
+                opts.device.as_deref().unwrap_or("(default device)")
+            ),
+            VideoInput::TestPattern(opts) => write!(f, "test pattern {}", opts.pattern),
+            VideoInput::Uri(uri, _) => write!(f, "stream {}", uri),
+            VideoInput::ImageSequence(pattern, opts) => write!(
+                f,
+                "images {} from {} at {} fps",
+                pattern, opts.start_index, opts.framerate
+            ),
+            VideoInput::Stdin => write!(f, "stdin"),
+            VideoInput::Concat(fnames, _) => {
+                write!(f, "{} files in a row: {}", fnames.len(), fnames.join(", "))
+            }
+            VideoInput::PictureInPicture(main, opts) => write!(
+                f,
+                "{} with webcam {} in the {} corner",
+                main,
+                opts.webcam.device.as_deref().unwrap_or("(default device)"),
+                opts.position
+            ),
+            VideoInput::Mosaic(tiles, _) => {
+                let tiles: Vec<String> = tiles.iter().map(|tile| tile.to_string()).collect();
+                write!(f, "mosaic of {} inputs: {}", tiles.len(), tiles.join(", "))
+            }
+            VideoInput::Raw(fname, opts) => write!(
+                f,
+                "raw {} {}x{} at {} fps {}",
+                opts.format, opts.width_height.0, opts.width_height.1, opts.framerate, fname
+            ),
+        }
+    }
+}
+
+/// A capture mode supported by a device
+pub struct DeviceMode {
+    /// Format delivered by the device, e.g. `raw YUY2` or `mjpeg`
+    pub format: String,
+    /// Resolution (or range of resolutions), e.g. `640x480`
+    pub resolution: String,
+    /// Supported framerates, e.g. `{ (fraction)30/1, (fraction)15/1 }`
+    pub framerates: String,
+}
+
+/// A video capture device
+pub struct DeviceInfo {
+    /// Human readable name
+    pub name: String,
+    /// Device node, e.g. `/dev/video0`, if known
+    pub path: Option<String>,
+    /// Supported capture modes
+    pub modes: Vec<DeviceMode>,
+}
+
+impl Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {}",
+            self.path.as_deref().unwrap_or("(unknown path)"),
+            self.name
+        )?;
+        for mode in &self.modes {
+            writeln!(
+                f,
+                "  {} {} @ {}",
+                mode.format, mode.resolution, mode.framerates
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Something that happened while the pipeline runs, reported to the handlers registered
+/// with [`Decoder::on_event`]
+pub enum Event {
+    /// A file of a split recording was closed and is complete
+    FileClosed {
+        /// Path of the file
+        path: PathBuf,
+        /// Running time of the pipeline when the file was closed
+        running_time: Duration,
+    },
+    /// A frame was written to an image file
+    FrameExtracted {
+        /// Path of the image
+        path: PathBuf,
+        /// Timestamp of the frame, from the start of the stream
+        timestamp: Duration,
+    },
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::FileClosed { path, running_time } => write!(
+                f,
+                "closed {} at {:.3}s",
+                path.display(),
+                running_time.as_secs_f64()
+            ),
+            Event::FrameExtracted { path, timestamp } => write!(
+                f,
+                "extracted {} at {:.3}s",
+                path.display(),
+                timestamp.as_secs_f64()
+            ),
+        }
+    }
+}
+
+/// List the available video capture devices along with the modes they support
+pub fn list_devices() -> Result<Vec<DeviceInfo>, Error> {
+    gst::devices::list_devices()
+}
+
+/// Write a contact sheet of the video file at `path`: a grid of evenly spaced frames in one image
+pub fn contact_sheet(path: &Path, opts: &ContactSheetOptions) -> Result<(), Error> {
+    gst::contactsheet::contact_sheet(path, opts)
 }
 
 /// Trait that defines the common interface for supported video manipulator structs
@@ -54,6 +200,10 @@ pub trait Decoder {
     fn new(input: VideoInput) -> Result<Arc<Mutex<Self>>, Error>;
     /// Add decoders, encoders and filters
     fn build(self_rc: Arc<Mutex<Self>>, opts: DecoderOptions) -> Result<(), Error>;
+    /// Describe the built pipeline as an equivalent command line, without running it
+    fn describe(&self) -> String;
     /// Parse the input file and output the result to the screen
     fn run(&mut self) -> Result<(), Error>;
+    /// Call `handler` for each [`Event`] while the pipeline runs
+    fn on_event<F: Fn(&Event) + Send + 'static>(&mut self, handler: F);
 }